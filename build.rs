@@ -0,0 +1,81 @@
+//! Fingerprints the sources this crate is built from (its own and those
+//! of its path dependencies, plus `Cargo.lock`), so that cached results
+//! are not reused after the simulation libraries change.
+
+use std::path::{Path, PathBuf};
+
+#[allow(dead_code)]
+#[path = "src/hashing.rs"]
+mod hashing;
+
+use hashing::ContentHash;
+
+/// The directories of the path dependencies listed in a `Cargo.toml`
+fn path_dependencies(manifest: &str) -> Vec<PathBuf> {
+    let mut section = "";
+    let mut dirs = Vec::new();
+    for line in manifest.lines().map(|l| l.trim()) {
+        if line.starts_with('[') {
+            section = line;
+            continue;
+        }
+        if section != "[dependencies]" {
+            continue;
+        }
+        let path = line
+            .find("path")
+            .map(|i| &line[i..])
+            .and_then(|rest| rest.find('"').map(|i| &rest[i + 1..]))
+            .and_then(|rest| rest.find('"').map(|i| &rest[..i]));
+        if let Some(path) = path {
+            dirs.push(PathBuf::from(path));
+        }
+    }
+    dirs
+}
+
+/// Every Rust source and manifest within a directory, sorted
+fn source_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            if path.file_name().map_or(false, |n| n != "target" && n != ".git") {
+                source_files(&path, files);
+            }
+        } else if path.extension().map_or(false, |e| e == "rs") || path.file_name().map_or(false, |n| n == "Cargo.toml") {
+            files.push(path);
+        }
+    }
+}
+
+fn main() {
+    let manifest = std::fs::read_to_string("Cargo.toml").expect("Could not read Cargo.toml");
+
+    let mut dirs = vec![PathBuf::from("src")];
+    dirs.extend(path_dependencies(&manifest));
+
+    let mut files = vec![PathBuf::from("Cargo.toml")];
+    for dir in dirs.iter() {
+        source_files(dir, &mut files);
+        println!("cargo:rerun-if-changed={}", dir.display());
+    }
+    files.sort();
+    if Path::new("Cargo.lock").exists() {
+        files.push(PathBuf::from("Cargo.lock"));
+    }
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let mut hash = ContentHash::new();
+    for file in files.iter() {
+        hash.update_str(&file.to_string_lossy());
+        if hash.update_file(&file.to_string_lossy()).is_err() {
+            hash.update_str("unreadable");
+        }
+    }
+    println!("cargo:rustc-env=SIMPLE_SOURCE_FINGERPRINT={}", hash.hex());
+}
@@ -0,0 +1,197 @@
+use std::panic;
+use std::path::Path;
+use std::time::Instant;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::ResultCache;
+use crate::case::{Case, CaseDefinition};
+use crate::csv_export::CsvDialect;
+use crate::energy_balance::EnergyBalanceConfig;
+use crate::forecast::ForecastConfig;
+use crate::overrides::StateOverrides;
+use crate::runner::{run_case, simulation_period};
+use crate::weather_sources::WeatherSpec;
+
+/// A single run in a batch
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchEntry {
+    /// The name of the case (e.g. `case1`)
+    pub case: String,
+
+    /// The weather: an EPW file or a JSON weather specification
+    pub weather: String,
+
+    /// The directory where the results are written. If not given,
+    /// a directory named after the case and the weather file is
+    /// created inside the batch's output directory.
+    #[serde(default)]
+    pub output_dir: Option<String>,
+
+    /// The forecast seen by the occupant, if not the actual weather
+    #[serde(default)]
+    pub forecast: Option<ForecastConfig>,
+
+    /// The seed of the random numbers used in the simulation
+    #[serde(default)]
+    pub seed: u64,
+
+    /// If given, the heat balance of the spaces is checked and exported
+    #[serde(default)]
+    pub energy_balance: Option<EnergyBalanceConfig>,
+
+    /// A file with values that replace the simulated ones (see `StateOverrides`)
+    #[serde(default)]
+    pub overrides: Option<String>,
+}
+
+/// A set of runs, read from a JSON file such as
+///
+/// ```json
+/// {
+///     "output_dir": "batch_results",
+///     "runs": [
+///         {"case": "case1", "weather": "Santiago.epw"},
+///         {"case": "case3_busy", "weather": "Santiago.epw"}
+///     ]
+/// }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchManifest {
+    /// The directory where the results and the batch index are written
+    #[serde(default = "default_output_dir")]
+    pub output_dir: String,
+
+    pub runs: Vec<BatchEntry>,
+}
+
+fn default_output_dir() -> String {
+    "batch_results".to_string()
+}
+
+impl BatchManifest {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        match serde_json::from_str(&data) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(format!("Could not parse batch manifest '{}': {}", filename, e)),
+        }
+    }
+
+    /// The directory where the results of a run are written
+    fn run_output_dir(&self, entry: &BatchEntry) -> String {
+        match &entry.output_dir {
+            Some(dir) => dir.clone(),
+            None => {
+                let weather = Path::new(&entry.weather)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "weather".to_string());
+                Path::new(&self.output_dir)
+                    .join(format!("{}_{}", entry.case, weather))
+                    .to_string_lossy()
+                    .to_string()
+            }
+        }
+    }
+}
+
+/// What happened with a run of a batch
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchRecord {
+    pub case: String,
+    pub weather: String,
+    pub output_dir: String,
+
+    /// Whether the run finished successfully
+    pub success: bool,
+
+    /// The error, if the run failed
+    pub error: Option<String>,
+
+    /// Wall-clock time taken by the run, in seconds
+    pub wall_time: f64,
+
+    /// The files written by the run
+    pub files: Vec<String>,
+
+    /// Whether the results were taken from the cache instead of simulated
+    #[serde(default)]
+    pub cached: bool,
+}
+
+/// Runs all the entries of a manifest in parallel. A failing
+/// run (even one that panics) does not stop the rest. Results
+/// found in the `cache` are reused.
+pub fn run_batch(manifest: &BatchManifest, dialect: &CsvDialect, cache: Option<&ResultCache>) -> Vec<BatchRecord> {
+    manifest
+        .runs
+        .par_iter()
+        .map(|entry| {
+            let output_dir = manifest.run_output_dir(entry);
+            let timer = Instant::now();
+
+            let result = panic::catch_unwind(|| -> Result<(Vec<String>, bool), String> {
+                let mut definition = CaseDefinition::new(Case::from_name(&entry.case)?);
+                definition.options.forecast = entry.forecast.clone();
+                definition.options.seed = entry.seed;
+                definition.reports.energy_balance = entry.energy_balance.clone();
+                if let Some(file) = &entry.overrides {
+                    definition.options.overrides = Some(StateOverrides::from_file(file, &definition.building)?);
+                }
+                // Check and summarize the weather before simulating, even if the results are cached
+                if let Some(report) = WeatherSpec::from_arg(&entry.weather)?.check(&simulation_period())? {
+                    println!("{} with {}:\n{}", entry.case, entry.weather, report);
+                }
+                let output = run_case(&definition, &entry.weather, &output_dir, dialect, cache)?;
+                Ok((output.files, output.cached))
+            });
+            let result = match result {
+                Ok(v) => v,
+                Err(e) => Err(panic_message(e)),
+            };
+
+            let (success, error, files, cached) = match result {
+                Ok((files, cached)) => (true, None, files, cached),
+                Err(e) => (false, Some(e), Vec::new(), false),
+            };
+            BatchRecord {
+                case: entry.case.clone(),
+                weather: entry.weather.clone(),
+                output_dir,
+                success,
+                error,
+                wall_time: timer.elapsed().as_secs_f64(),
+                files,
+                cached,
+            }
+        })
+        .collect()
+}
+
+/// Gets the message out of a panic
+fn panic_message(e: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = e.downcast_ref::<&str>() {
+        format!("panicked: {}", s)
+    } else if let Some(s) = e.downcast_ref::<String>() {
+        format!("panicked: {}", s)
+    } else {
+        "panicked".to_string()
+    }
+}
+
+/// Writes the batch index, listing every run and its output files
+pub fn write_index(records: &[BatchRecord], filename: &str) -> Result<(), String> {
+    let content = match serde_json::to_string_pretty(records) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Could not serialize batch index: {}", e)),
+    };
+    match std::fs::write(filename, content) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+    }
+}
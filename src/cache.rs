@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::Value;
+
+use crate::case::CaseDefinition;
+use crate::hashing::ContentHash;
+use crate::runner::{simulation_period, STEPS_PER_HOUR};
+use crate::weather_sources::WeatherSpec;
+
+/// The directory used for caching results, unless told otherwise
+pub const DEFAULT_CACHE_DIR: &str = ".simple_cache";
+
+/// Distinguishes the temporary files of parallel writes
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory where simulation results are kept, keyed by a hash of
+/// everything that affects them: the case definition (building, occupant
+/// and run options, but not the `ReportOptions`, which only post-process
+/// the results), the weather (and the files it reads), the simulated
+/// period and a fingerprint of the sources of this crate and of the
+/// simulation libraries (see `build.rs`).
+#[derive(Clone, Debug)]
+pub struct ResultCache {
+    pub dir: PathBuf,
+
+    /// Ignore cached results (they are still overwritten with new ones)
+    pub force: bool,
+}
+
+impl ResultCache {
+    pub fn new(dir: &str, force: bool) -> Self {
+        ResultCache {
+            dir: PathBuf::from(dir),
+            force,
+        }
+    }
+
+    /// The key of the results of a case simulated with a certain weather
+    pub fn key(definition: &CaseDefinition, weather: &WeatherSpec) -> Result<String, String> {
+        let mut hash = ContentHash::new();
+        hash.update_str(env!("CARGO_PKG_NAME"));
+        hash.update_str(env!("CARGO_PKG_VERSION"));
+        hash.update_str(env!("SIMPLE_SOURCE_FINGERPRINT"));
+        let mut definition = match serde_json::to_value(definition) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize case definition: {}", e)),
+        };
+        if let Some(definition) = definition.as_object_mut() {
+            definition.remove("reports");
+        }
+        hash.update_str(&definition.to_string());
+        hash.update_str(&weather.hash()?);
+        let (start, end) = simulation_period();
+        hash.update_str(&format!("{} {} {}", start, end, STEPS_PER_HOUR));
+        Ok(hash.hex())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// The cached results with a certain key, if there are any and `force` is not set
+    pub fn load(&self, key: &str) -> Result<Option<Value>, String> {
+        let path = self.path(key);
+        if self.force || !path.exists() {
+            return Ok(None);
+        }
+        let data = match std::fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", path.to_string_lossy(), e)),
+        };
+        match serde_json::from_str(&data) {
+            Ok(v) => Ok(Some(v)),
+            // A corrupt entry is just a cache miss
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Stores some results
+    pub fn store(&self, key: &str, results: &Value) -> Result<(), String> {
+        if let Err(e) = std::fs::create_dir_all(&self.dir) {
+            return Err(format!("Could not create directory '{}': {}", self.dir.to_string_lossy(), e));
+        }
+        let content = match serde_json::to_string(results) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize results: {}", e)),
+        };
+
+        // Write and rename, so that parallel runs never see half-written entries
+        let path = self.path(key);
+        let write = WRITES.fetch_add(1, Ordering::SeqCst);
+        let tmp = Path::new(&self.dir).join(format!("{}.{}.{}.tmp", key, std::process::id(), write));
+        if let Err(e) = std::fs::write(&tmp, content) {
+            return Err(format!("Could not write file '{}': {}", tmp.to_string_lossy(), e));
+        }
+        match std::fs::rename(&tmp, &path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", path.to_string_lossy(), e)),
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use std::fmt;
+
+use calendar::date::Date;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use weather::Weather;
+
+use crate::case::{Case, CaseDefinition};
+use crate::csv_export::{write_file, CsvDialect};
+use crate::date_utils::{date_from_hour_of_year, hour_of_year};
+use crate::measured_data::{compare, MeasuredData, MeasuredDataConfig, SensorFit, SensorKind};
+use crate::rng::Rng;
+use crate::runner::{simulate_period, STEPS_PER_HOUR};
+use crate::sensitivity::Factor;
+use crate::weather_sources::WeatherSpec;
+
+/// Hourly CV(RMSE) below which a model is considered calibrated (ASHRAE Guideline 14), in %
+pub const MAX_CV_RMSE: f64 = 30.;
+
+/// Hourly NMBE (in absolute value) below which a model is considered calibrated (ASHRAE Guideline 14), in %
+pub const MAX_NMBE: f64 = 10.;
+
+fn default_samples() -> usize {
+    16
+}
+
+fn default_rounds() -> usize {
+    4
+}
+
+fn default_steps_per_hour() -> usize {
+    STEPS_PER_HOUR
+}
+
+fn default_warm_up_days() -> usize {
+    7
+}
+
+fn default_temperature_scale() -> f64 {
+    1.0
+}
+
+fn default_window_scale() -> f64 {
+    0.1
+}
+
+/// The errors of the sensors that count as one unit of the objective
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectiveScales {
+    /// RMSE of a temperature sensor, in K
+    #[serde(default = "default_temperature_scale")]
+    pub temperature: f64,
+
+    /// Fraction of measurements of a window sensor whose state (open or
+    /// closed) is not matched by the simulation
+    #[serde(default = "default_window_scale")]
+    pub window: f64,
+}
+
+impl Default for ObjectiveScales {
+    fn default() -> Self {
+        ObjectiveScales {
+            temperature: default_temperature_scale(),
+            window: default_window_scale(),
+        }
+    }
+}
+
+/// The configuration of a calibration, read from a JSON file such as
+///
+/// ```json
+/// {
+///     "case": "case1",
+///     "weather": "Santiago_2021.epw",
+///     "measured": {
+///         "file": "apartment_3.csv",
+///         "columns": {"month": 0, "day": 1, "hour": 2},
+///         "sensors": [{"column": 3, "kind": "temperature", "space": "Bedroom 1"}]
+///     },
+///     "variables": [
+///         {"name": "concrete_thickness", "min": 0.1, "max": 0.3},
+///         {"name": "proactivity", "min": 0.0, "max": 1.0}
+///     ],
+///     "seed": 1234
+/// }
+/// ```
+///
+/// Each round samples `samples` points around the best one found so
+/// far, halving the ranges of the variables, and keeps the one with the
+/// lowest objective (see `ObjectiveScales`). Without `variables`, the
+/// case is only compared with the measurements.
+///
+/// The simulation starts `warm_up_days` before the first measurement, so
+/// the weather must cover them too.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CalibrationConfig {
+    /// The name of the case (e.g. `case1`)
+    pub case: String,
+
+    /// The weather during the measurements: an EPW file or a JSON weather specification
+    pub weather: String,
+
+    pub measured: MeasuredDataConfig,
+
+    /// The building and occupant parameters tuned (see `CaseDefinition::set`)
+    #[serde(default)]
+    pub variables: Vec<Factor>,
+
+    #[serde(default = "default_samples")]
+    pub samples: usize,
+
+    #[serde(default = "default_rounds")]
+    pub rounds: usize,
+
+    #[serde(default)]
+    pub seed: u64,
+
+    #[serde(default = "default_steps_per_hour")]
+    pub steps_per_hour: usize,
+
+    /// Days simulated before the measurements, so the results
+    /// do not depend on the initial state
+    #[serde(default = "default_warm_up_days")]
+    pub warm_up_days: usize,
+
+    #[serde(default)]
+    pub scales: ObjectiveScales,
+}
+
+impl CalibrationConfig {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        match serde_json::from_str(&data) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(format!("Could not parse calibration configuration '{}': {}", filename, e)),
+        }
+    }
+}
+
+/// A set of parameters tried, and how well it matched the measurements
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CalibrationPoint {
+    pub parameters: Vec<(String, f64)>,
+
+    /// The fit of each sensor, or the error that prevented simulating
+    pub fits: Result<Vec<SensorFit>, String>,
+
+    /// The mean normalised error of the sensors (lower is better)
+    pub objective: f64,
+}
+
+/// The results of a calibration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CalibrationResults {
+    pub config: CalibrationConfig,
+    pub points: Vec<CalibrationPoint>,
+}
+
+/// The mean error of the sensors, each divided by its scale: the RMSE of
+/// temperatures and the fraction of mismatched states of windows. The
+/// CV(RMSE) is not used, as it depends on the origin of the temperature
+/// scale and does not exist for windows that are mostly closed.
+fn objective(fits: &[SensorFit], scales: &ObjectiveScales) -> f64 {
+    let values: Vec<f64> = fits
+        .iter()
+        .filter(|f| f.fit.count > 0)
+        .filter_map(|f| match f.sensor.kind {
+            SensorKind::Temperature => Some(f.fit.rmse / scales.temperature),
+            SensorKind::Window => f.fit.agreement.map(|v| (1. - v) / scales.window),
+        })
+        .filter(|v| v.is_finite())
+        .collect();
+    if values.is_empty() {
+        return std::f64::INFINITY;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Simulates a case with some of its parameters changed, and compares it with the measurements
+fn evaluate(
+    base: &CaseDefinition,
+    parameters: Vec<(String, f64)>,
+    weather: &dyn Weather,
+    measured: &MeasuredData,
+    period: (Date, Date),
+    n: usize,
+    scales: &ObjectiveScales,
+) -> CalibrationPoint {
+    let fits = {
+        let mut definition = base.clone();
+        parameters
+            .iter()
+            .try_for_each(|(name, value)| definition.set(name, *value))
+            .and_then(|_| simulate_period(&definition, weather, period, n))
+            .and_then(|simulation| compare(measured, &simulation.results, &definition.building))
+    };
+    let objective = match &fits {
+        Ok(fits) => objective(fits, scales),
+        Err(_) => std::f64::INFINITY,
+    };
+    CalibrationPoint {
+        parameters,
+        fits,
+        objective,
+    }
+}
+
+/// The point that best matches the measurements
+fn best(points: &[CalibrationPoint]) -> Option<&CalibrationPoint> {
+    points
+        .iter()
+        .filter(|p| p.objective.is_finite())
+        .min_by(|a, b| a.objective.partial_cmp(&b.objective).unwrap_or(std::cmp::Ordering::Equal))
+}
+
+/// The whole days that contain the measurements, preceded by some warm-up days
+fn simulation_period(measured: &MeasuredData, warm_up_days: usize) -> Result<(Date, Date), String> {
+    let (first, last) = match measured.period() {
+        Some(v) => v,
+        None => return Err("There are no measurements to calibrate against".to_string()),
+    };
+    let first_day = Date {
+        month: first.month,
+        day: first.day,
+        hour: 0.0,
+    };
+    // The simulation cannot start in the previous year
+    let start = hour_of_year(&first_day) - 24. * warm_up_days as f64;
+    if start < 0.0 {
+        return Err(format!(
+            "The measurements start on {}/{}, so {} warm-up days would start in the previous year... reduce 'warm_up_days'",
+            first.day, first.month, warm_up_days
+        ));
+    }
+    let start = date_from_hour_of_year(start);
+    let mut end = Date {
+        month: last.month,
+        day: last.day,
+        hour: 0.0,
+    };
+    end.add_days(1);
+    Ok((start, end))
+}
+
+/// Calibrates a case against measurements. The weather only needs to
+/// cover the period of the measurements and the warm-up days before it.
+pub fn run_calibration(config: &CalibrationConfig) -> Result<CalibrationResults, String> {
+    let case = Case::from_name(&config.case)?;
+    let measured = MeasuredData::from_file(&config.measured)?;
+    let period = simulation_period(&measured, config.warm_up_days)?;
+    if config.scales.temperature <= 0. || config.scales.window <= 0. {
+        return Err("The scales of the objective must be positive".to_string());
+    }
+    let weather = WeatherSpec::from_arg(&config.weather)?.build(&period)?;
+    let base = CaseDefinition::new(case);
+    let names: Vec<&str> = config.variables.iter().map(|v| v.name.as_str()).collect();
+    CaseDefinition::check_parameters(&names)?;
+    for v in config.variables.iter() {
+        if v.min >= v.max {
+            return Err(format!("Variable '{}' has an empty range", v.name));
+        }
+        base.clone().set(&v.name, v.min)?;
+    }
+    for series in measured.series.iter() {
+        series.sensor.element_index(&base.building)?;
+    }
+
+    let try_point = |parameters: Vec<(String, f64)>| evaluate(&base, parameters, &*weather, &measured, period, config.steps_per_hour, &config.scales);
+
+    // The case as it is
+    let mut points = vec![try_point(Vec::new())];
+    if config.variables.is_empty() {
+        return Ok(CalibrationResults {
+            config: config.clone(),
+            points,
+        });
+    }
+
+    let mut rng = Rng::new(config.seed);
+    let mut centre: Vec<f64> = config.variables.iter().map(|v| 0.5 * (v.min + v.max)).collect();
+    let mut width = 1.0;
+    for _ in 0..config.rounds {
+        let candidates: Vec<Vec<(String, f64)>> = (0..config.samples)
+            .map(|_| {
+                config
+                    .variables
+                    .iter()
+                    .zip(centre.iter())
+                    .map(|(v, c)| {
+                        let half = 0.5 * width * (v.max - v.min);
+                        (v.name.clone(), rng.range((c - half).max(v.min), (c + half).min(v.max)))
+                    })
+                    .collect()
+            })
+            .collect();
+        points.extend(candidates.into_par_iter().map(try_point).collect::<Vec<CalibrationPoint>>());
+
+        if let Some(point) = best(&points).filter(|p| !p.parameters.is_empty()) {
+            centre = point.parameters.iter().map(|(_, v)| *v).collect();
+        }
+        width *= 0.5;
+    }
+
+    Ok(CalibrationResults {
+        config: config.clone(),
+        points,
+    })
+}
+
+impl CalibrationResults {
+    /// The point that best matches the measurements
+    pub fn best(&self) -> Option<&CalibrationPoint> {
+        best(&self.points)
+    }
+
+    /// Writes a table with one row per point tried
+    pub fn write_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header: Vec<String> = self.config.variables.iter().map(|v| v.name.clone()).collect();
+        header.push("objective".to_string());
+        for sensor in self.config.measured.sensors.iter() {
+            header.push(format!("{} RMSE", sensor.name()));
+            header.push(format!("{} CV(RMSE) [%]", sensor.name()));
+            header.push(format!("{} NMBE [%]", sensor.name()));
+            header.push(format!("{} Agreement [%]", sensor.name()));
+        }
+        header.push("Error".to_string());
+
+        let rows: Vec<Vec<String>> = self
+            .points
+            .iter()
+            .map(|p| {
+                let mut row: Vec<String> = self
+                    .config
+                    .variables
+                    .iter()
+                    .map(|var| {
+                        p.parameters
+                            .iter()
+                            .find(|(name, _)| *name == var.name)
+                            .map(|(_, v)| dialect.number(*v))
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                row.push(dialect.number(p.objective));
+                match &p.fits {
+                    Ok(fits) => {
+                        for fit in fits {
+                            row.push(dialect.number(fit.fit.rmse));
+                            row.push(dialect.number(fit.fit.cv_rmse));
+                            row.push(dialect.number(fit.fit.nmbe));
+                            row.push(fit.fit.agreement.map(|v| dialect.number(100. * v)).unwrap_or_default());
+                        }
+                        row.push(String::new());
+                    }
+                    Err(e) => {
+                        for _ in 0..4 * self.config.measured.sensors.len() {
+                            row.push(String::new());
+                        }
+                        row.push(dialect.text(e));
+                    }
+                }
+                row
+            })
+            .collect();
+
+        write_file(filename, &header, &rows, dialect)
+    }
+
+    /// Writes the whole calibration as JSON
+    pub fn write_json(&self, filename: &str) -> Result<(), String> {
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize calibration results: {}", e)),
+        };
+        match std::fs::write(filename, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+        }
+    }
+}
+
+impl fmt::Display for CalibrationResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let best = match self.best() {
+            Some(v) => v,
+            None => return writeln!(f, "No simulation could be compared with the measurements"),
+        };
+        writeln!(f, "Best of {} simulations:", self.points.len())?;
+        if best.parameters.is_empty() {
+            writeln!(f, "    (the parameters of the case)")?;
+        }
+        for (name, value) in best.parameters.iter() {
+            writeln!(f, "    {} = {:.4}", name, value)?;
+        }
+        writeln!(f, "Objective: {:.4}", best.objective)?;
+        writeln!(
+            f,
+            "{:<28} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            "Sensor", "Count", "RMSE", "CV(RMSE)", "NMBE", "Agreement"
+        )?;
+        if let Ok(fits) = &best.fits {
+            for fit in fits {
+                write!(f, "{}", fit)?;
+                if fit.fit.cv_rmse.abs() > MAX_CV_RMSE || fit.fit.nmbe.abs() > MAX_NMBE {
+                    write!(f, " (outside ASHRAE Guideline 14)")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,762 @@
+use serde::{Deserialize, Serialize};
+
+use simulation_state::simulation_state::SimulationState;
+use people::person::Person;
+use people::perceptions::Perception;
+use schedule::constant::ScheduleConstant;
+use polynomial::*;
+
+use building_model::building::Building;
+use building_model::material::MaterialProperties;
+use building_model::substance::SubstanceProperties;
+use building_model::object_trait::ObjectTrait;
+use building_model::boundary::Boundary;
+use building_model::fenestration::{FenestrationPositions,FenestrationType};
+use building_model::heating_cooling::HeatingCoolingKind;
+
+use geometry3d::loop3d::Loop3D;
+use geometry3d::point3d::Point3D;
+use geometry3d::polygon3d::Polygon3D;
+
+use crate::RunOptions;
+use crate::runner::ReportOptions;
+
+fn get_squared_polygon(outer_area: f64, inner_area: f64)->Polygon3D{
+    assert!(outer_area > inner_area);
+
+    // Create the outer part
+    let mut the_loop = Loop3D::new();
+    let l = outer_area.sqrt();
+
+    the_loop.push( Point3D::new(-l, -l, 0.)).unwrap();
+    the_loop.push( Point3D::new(l, -l, 0.)).unwrap();
+    the_loop.push( Point3D::new(l, l, 0.)).unwrap();
+    the_loop.push( Point3D::new(-l, l, 0.)).unwrap();
+    the_loop.close().unwrap();
+    
+    let mut p = Polygon3D::new(the_loop).unwrap();
+
+    if inner_area > 0.0 {
+
+        let l = inner_area.sqrt();
+        let mut the_inner_loop = Loop3D::new();
+        the_inner_loop.push( Point3D::new(-l, -l, 0.)).unwrap();
+        the_inner_loop.push( Point3D::new(l, -l, 0.)).unwrap();
+        the_inner_loop.push( Point3D::new(l, l, 0.)).unwrap();
+        the_inner_loop.push( Point3D::new(-l, l, 0.)).unwrap();
+        the_inner_loop.close().unwrap();
+        p.cut_hole(the_inner_loop.clone()).unwrap();
+    }
+    p
+}
+
+#[allow(dead_code)]
+fn add_wall_between_spaces(building: &mut Building, space_a_index: usize, space_b_index: usize, area: f64, wall_construction_index: usize){
+    let space_a_name: String;
+    let space_b_name: String;
+    {
+        let space_a = building.get_space(space_a_index).unwrap();
+        space_a_name = space_a.name().clone();
+        let space_b = building.get_space(space_b_index).unwrap();
+        space_b_name = space_b.name().clone();
+    }
+
+    // Square with no windows
+    let p = get_squared_polygon(area, 0.0);
+
+    // Add surface
+    let surface_index = building.add_surface(format!("Surface between Spaces {} and {}", space_a_name, space_b_name));
+    building.set_surface_construction(surface_index,wall_construction_index).unwrap();
+    building.set_surface_polygon(surface_index, p).unwrap();
+    
+    building.set_surface_front_boundary(surface_index, Boundary::Space(space_a_index)).unwrap();
+    building.set_surface_back_boundary(surface_index, Boundary::Space(space_b_index)).unwrap();
+
+}
+
+/// Adds a wall to a space... can have a window.
+fn add_wall_to_space(case: Case, building: &mut Building, state: &mut SimulationState, space_index : usize, wall_area: f64, window_area: f64, wall_construction_index: usize, window_construction_index: usize){
+    assert!(wall_area > window_area);
+
+    let space_name : String;
+    {
+        let space = building.get_space(space_index).unwrap();        
+        space_name = space.name().clone();
+    }
+
+    let p = get_squared_polygon(wall_area, window_area);
+
+    // Add surface
+    let surface_index = building.add_surface(format!("Outer Surface {}", space_name));
+    building.set_surface_construction(surface_index,wall_construction_index).unwrap();
+    building.set_surface_polygon(surface_index, p).unwrap();
+    
+    building.set_surface_front_boundary(surface_index, Boundary::Space(space_index)).unwrap();
+
+    // Add window.   
+    if window_area > 0.0 {
+        let window_polygon = get_squared_polygon(window_area, 0.0);
+
+        let position = if case.has_control(){
+            FenestrationPositions::Binary
+        }else{
+            FenestrationPositions::FixedClosed
+        };
+        
+        let window_index = building.add_fenestration(state, format!("Window in space {}", space_name), position, FenestrationType::Window);
+        building.set_fenestration_construction(window_index, window_construction_index).unwrap();     
+        building.set_fenestration_polygon(window_index, window_polygon).unwrap();
+        building.set_fenestration_front_boundary(window_index, Boundary::Space(space_index)).unwrap();
+    }     
+
+}
+
+
+fn add_space(case: Case, parameters: &BuildingParameters, building: &mut Building, state: &mut SimulationState, name: &str, length: f64, width: f64, height: f64, importance: f64) -> usize {
+    // Volume
+    let volume = length * width * height;
+    let space_index = building.add_space(name.to_string());
+    building.set_space_volume(space_index, volume).unwrap();
+
+    let importance_schedule = Box::new(ScheduleConstant::new(importance));
+
+    building.set_space_importance(space_index, importance_schedule).unwrap();
+
+    
+    if case.has_control(){
+        // Heater
+        building.add_heating_cooling_to_space(state,space_index, HeatingCoolingKind::ElectricHeating).unwrap();
+        building.set_space_max_heating_power(space_index, parameters.heater_power).unwrap();
+    
+        // Lights
+        building.add_luminaire_to_space(state, space_index).unwrap();
+        building.set_space_max_lighting_power(space_index, parameters.lighting_power).unwrap();
+    }
+    
+    // Return space index
+    space_index
+}
+
+fn add_construction(building: &mut Building, substance_name: &'static str, properties: SubstanceProperties, thickness: f64)->usize{
+
+    let substance_index = building.add_substance(substance_name.to_string());
+
+    building.set_substance_properties(substance_index, properties).unwrap();
+
+    // add material
+    let mat_index = building.add_material(format!("{}mm {}", thickness*1000.0, substance_name.to_string()));
+    building.set_material_properties(mat_index, MaterialProperties{
+        thickness: thickness
+    }).unwrap();
+
+    building.set_material_substance(mat_index, substance_index).unwrap();
+
+    // Add construction
+    let construction_index = building.add_construction(format!("{} construction", substance_name));
+    building.add_material_to_construction(construction_index, mat_index).unwrap();
+
+    construction_index
+    
+}
+
+/// The design variables of the building
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BuildingParameters {
+    /// Height of the spaces, in m
+    pub building_height: f64,
+
+    /// Thickness of the concrete walls, in m
+    pub concrete_thickness: f64,
+
+    /// Thickness of the window glass, in m
+    pub glass_thickness: f64,
+
+    /* Widths of the windows (which are 1m high), in m */
+    pub bedroom_1_window_width: f64,
+    pub bedroom_2_window_width: f64,
+    pub living_room_window_width: f64,
+    pub bathroom_window_width: f64,
+    pub kitchen_window_width: f64,
+    pub hallway_window_width: f64,
+
+    /// Maximum heating power of each space, in W
+    pub heater_power: f64,
+
+    /// Maximum lighting power of each space, in W
+    pub lighting_power: f64,
+}
+
+impl Default for BuildingParameters {
+    fn default()->Self{
+        BuildingParameters{
+            building_height: 2.5,
+            concrete_thickness: 180.0/1000.0,
+            glass_thickness: 3.0/1000.0,
+            bedroom_1_window_width: 1.7,
+            bedroom_2_window_width: 0.9,
+            living_room_window_width: 2.3,
+            bathroom_window_width: 0.5,
+            kitchen_window_width: 1.3,
+            hallway_window_width: 0.9,
+            heater_power: 1500.,
+            lighting_power: 180.,
+        }
+    }
+}
+
+impl BuildingParameters {
+
+    /// The names of the parameters accepted by `set`
+    pub fn parameter_names()->&'static [&'static str]{
+        &[
+            "building_height",
+            "concrete_thickness",
+            "glass_thickness",
+            "bedroom_1_window_width",
+            "bedroom_2_window_width",
+            "living_room_window_width",
+            "bathroom_window_width",
+            "kitchen_window_width",
+            "hallway_window_width",
+            "window_scale",
+            "heater_power",
+            "lighting_power",
+        ]
+    }
+
+    /// Sets a parameter by name. `window_scale` multiplies 
+    /// the default widths of all windows by the value given, so it
+    /// replaces any width set before (see `CaseDefinition::check_parameters`).
+    pub fn set(&mut self, name: &str, value: f64)->Result<(),String>{
+        match name {
+            "building_height" => self.building_height = value,
+            "concrete_thickness" => self.concrete_thickness = value,
+            "glass_thickness" => self.glass_thickness = value,
+            "bedroom_1_window_width" => self.bedroom_1_window_width = value,
+            "bedroom_2_window_width" => self.bedroom_2_window_width = value,
+            "living_room_window_width" => self.living_room_window_width = value,
+            "bathroom_window_width" => self.bathroom_window_width = value,
+            "kitchen_window_width" => self.kitchen_window_width = value,
+            "hallway_window_width" => self.hallway_window_width = value,
+            "window_scale" => {
+                let default = BuildingParameters::default();
+                self.bedroom_1_window_width = default.bedroom_1_window_width * value;
+                self.bedroom_2_window_width = default.bedroom_2_window_width * value;
+                self.living_room_window_width = default.living_room_window_width * value;
+                self.bathroom_window_width = default.bathroom_window_width * value;
+                self.kitchen_window_width = default.kitchen_window_width * value;
+                self.hallway_window_width = default.hallway_window_width * value;
+            },
+            "heater_power" => self.heater_power = value,
+            "lighting_power" => self.lighting_power = value,
+            _ => return Err(format!("Unknown building parameter '{}'", name))
+        }
+        Ok(())
+    }
+
+    /// The name of the space, the perimeter of its exterior wall (in m) 
+    /// and the width of its window (in m), for every space
+    fn exterior_walls(&self)->[(&'static str, f64, f64);6]{
+        [
+            ("Bedroom 1", (1557.0 + 4115. + 3891.)/1000.0, self.bedroom_1_window_width),
+            ("Bedroom 2", (3000.0 + 2339.)/1000.0, self.bedroom_2_window_width),
+            ("Living room", (4000.0 + 4300.)/1000.0, self.living_room_window_width),
+            ("Bathroom", 1.9, self.bathroom_window_width),
+            ("Kitchen", (2400.0 + 4200.)/1000.0, self.kitchen_window_width),
+            ("Hallway", 1.0, self.hallway_window_width),
+        ]
+    }
+
+    /// The geometry of every space, in the order in which `create_building`
+    /// adds them (which is also the order of their walls and windows)
+    pub fn space_geometry(&self)->Vec<SpaceGeometry>{
+        // Floor dimensions (in m), as in create_building
+        let floors = [(3.6, 4.0), (2.4, 3.0), (4.6, 4.0), (1.9, 2.4), (2.4, 4.3), (1.0, 4.3)];
+        self.exterior_walls().iter().zip(floors.iter()).map(|((name, perimeter, window_width), (length, width))|{
+            let window_area = *window_width; // windows are 1m high
+            SpaceGeometry{
+                name: name.to_string(),
+                volume: length * width * self.building_height,
+                wall_area: perimeter * self.building_height - window_area,
+                window_area,
+            }
+        }).collect()
+    }
+
+    /// The index of the window of each space (in the order of
+    /// `space_geometry`), as windows are only added to spaces that have them
+    pub fn window_indices(&self)->Vec<Option<usize>>{
+        let mut next_window = 0;
+        self.exterior_walls().iter().map(|(_, _, window_width)|{
+            if *window_width > 0.0 {
+                next_window += 1;
+                Some(next_window - 1)
+            }else{
+                None
+            }
+        }).collect()
+    }
+
+    /// The ratio between the area of the windows and the gross
+    /// area of the exterior walls
+    pub fn window_to_wall_ratio(&self)->f64{
+        let walls = self.exterior_walls();
+        let window_area : f64 = walls.iter().map(|(_, _, w)| w).sum(); // windows are 1m high
+        let wall_area : f64 = walls.iter().map(|(_, p, _)| p * self.building_height).sum();
+        window_area / wall_area
+    }
+
+    /// Checks that the parameters describe a building that can be built
+    pub fn validate(&self)->Result<(),String>{
+        if self.building_height <= 0.0 {
+            return Err(format!("Building height must be positive... found {}", self.building_height));
+        }
+        if self.concrete_thickness <= 0.0 || self.glass_thickness <= 0.0 {
+            return Err("Concrete and glass thicknesses must be positive".to_string());
+        }
+        if self.heater_power < 0.0 || self.lighting_power < 0.0 {
+            return Err("Heating and lighting powers cannot be negative".to_string());
+        }
+
+        // Windows are 1m high, and must fit in their walls
+        for (name, wall_perimeter, window_width) in self.exterior_walls().iter() {
+            if *window_width < 0.0 {
+                return Err(format!("Window width in {} cannot be negative", name));
+            }
+            if *window_width >= wall_perimeter * self.building_height {
+                return Err(format!("Window in {} ({} m2) does not fit in its wall ({} m2)", name, window_width, wall_perimeter * self.building_height));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Defines a 2-Bedroom + Livingroom + Bathroom + Kitchen + Hallway
+/// home. With the default parameters, all walls are made of 180mm 
+/// concrete, and the windows are 3mm glass.
+///
+/// When the case has control, every space has openable windows, a 1500W
+/// heater and 180W of switchable lights
+pub fn create_building(case: Case, parameters: &BuildingParameters, building: &mut Building, state: &mut SimulationState)->Result<(),String>{
+    parameters.validate()?;
+
+    // Set materials: All surfaces are made of 180mm concrete, except for windows.
+
+    /* ************* */
+    /* ADD MATERIALS */
+    /* ************* */
+    // Concrete
+    let concrete_construction_index = add_construction(building, "Concrete", SubstanceProperties{
+        thermal_conductivity: 2.33, // W/m.K            
+        specific_heat_capacity: 960., // J/kg.K
+        density: 2400., // kg/m3
+    }, parameters.concrete_thickness); // 180mm by default
+    
+    
+    // Glass
+    let glass_construction_index = add_construction(building, "Glass", SubstanceProperties{
+        thermal_conductivity: 2.33, // W/m.K            
+        specific_heat_capacity: 960., // J/kg.K
+        density: 2400., // kg/m3
+    }, parameters.glass_thickness); // 3mm by default
+    
+    /* ************ */
+    /* ADD GEOMETRY */
+    /* ************ */
+    
+    let building_height = parameters.building_height; // m
+
+    // 2B + Livingroom + Bathroom setup.
+    let bed_1      = add_space(case, parameters, building, state, "Bedroom 1",  3.6, 4.0, building_height, 1.0);
+    let bed_2      = add_space(case, parameters, building, state, "Bedroom 2",  2.4, 3.0, building_height, 1.0);
+    let livingroom = add_space(case, parameters, building, state, "Living room", 4.6, 4.0, building_height, 1.0);
+    let bathroom   = add_space(case, parameters, building, state, "Bathroom",   1.9, 2.4, building_height, 0.03);
+    let kitchen    = add_space(case, parameters, building, state, "Kitchen",    2.4, 4.3, building_height, 0.1);
+    let hallway    = add_space(case, parameters, building, state, "Hallway",    1.0, 4.3, building_height, 0.01);
+    
+    /* PERIMETER */
+
+    // bedroom 1
+    let wall_perimeter = (1557.0 + 4115. + 3891.)/1000.0;
+    let wall_area = wall_perimeter * building_height;
+    let window_perimeter = parameters.bedroom_1_window_width;
+    let window_area = window_perimeter; // assume that windows are 1m hight
+    add_wall_to_space(case, building, state, bed_1, wall_area, window_area, concrete_construction_index, glass_construction_index);
+
+    // bedroom 2
+    let wall_perimeter = (3000.0 + 2339.)/1000.0;
+    let wall_area = wall_perimeter * building_height;
+    let window_perimeter = parameters.bedroom_2_window_width;
+    let window_area = window_perimeter; // assume that windows are 1m hight
+    add_wall_to_space(case, building, state, bed_2, wall_area, window_area, concrete_construction_index, glass_construction_index);
+
+    // livingroom
+    let wall_perimeter = (4000.0 + 4300.)/1000.0;
+    let wall_area = wall_perimeter * building_height;
+    let window_perimeter = parameters.living_room_window_width;
+    let window_area = window_perimeter; // assume that windows are 1m hight
+    add_wall_to_space(case, building, state, livingroom, wall_area, window_area, concrete_construction_index, glass_construction_index);
+
+    // bathroom
+    let wall_perimeter = 1.9;
+    let wall_area = wall_perimeter * building_height;
+    let window_perimeter = parameters.bathroom_window_width;
+    let window_area = window_perimeter; // assume that windows are 1m hight
+    add_wall_to_space(case, building, state, bathroom, wall_area, window_area, concrete_construction_index, glass_construction_index);
+    /*
+    */
+    // kitchen
+    let wall_perimeter = (2400.0 + 4200.)/1000.0;
+    let wall_area = wall_perimeter * building_height;
+    let window_perimeter = parameters.kitchen_window_width;
+    let window_area = window_perimeter; // assume that windows are 1m hight
+    add_wall_to_space(case, building, state, kitchen, wall_area, window_area, concrete_construction_index, glass_construction_index);
+
+    // Hallway
+    let wall_perimeter = 1.0;
+    let wall_area = wall_perimeter * building_height;
+    let window_perimeter = parameters.hallway_window_width;
+    let window_area = window_perimeter; // assume that windows are 1m hight
+    add_wall_to_space(case, building, state, hallway, wall_area, window_area, concrete_construction_index, glass_construction_index);
+    /*
+    */
+
+    /* CONNECTIONS BETWEEN ZONES */
+    //add_wall_between_spaces(building, bed_1, hallway, 2.4 * building_height, concrete_construction_index);
+    //add_wall_between_spaces(building, bed_1, livingroom, 4.0 * building_height, concrete_construction_index);
+    
+    //add_wall_between_spaces(building, livingroom, hallway, 1.9 * building_height, concrete_construction_index);
+    //add_wall_between_spaces(building, livingroom, kitchen, 2.4 * building_height, concrete_construction_index);
+    
+    //add_wall_between_spaces(building, kitchen, hallway, 1.9 * building_height, concrete_construction_index);
+    //add_wall_between_spaces(building, kitchen, bathroom, 2.3 * building_height, concrete_construction_index);
+    
+    //add_wall_between_spaces(building, bathroom, hallway, 1.9 * building_height, concrete_construction_index);
+    //add_wall_between_spaces(building, bathroom, bed_2, 2.3 * building_height, concrete_construction_index);
+    
+    //add_wall_between_spaces(building, bed_2, hallway, (2.239 + 0.657) * building_height, concrete_construction_index);
+    
+    
+    Ok(())
+}
+
+/// The cases simulated in the thesis
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub enum Case {
+    Section1_1,
+    Section1_2WithControl,
+    Section1_2WithoutControl,
+    Section1_3NotBusy,
+    Section1_3Busy
+}
+
+impl Case {
+
+    /// All the cases
+    pub fn all()->[Case;5]{
+        [
+            Case::Section1_1,
+            Case::Section1_2WithoutControl,
+            Case::Section1_2WithControl,
+            Case::Section1_3Busy,
+            Case::Section1_3NotBusy
+        ]
+    }
+
+    /// Gets a case from the name used in the command line
+    pub fn from_name(name: &str)->Result<Case,String>{
+        match Case::all().iter().find(|c| c.name() == name) {
+            Some(c) => Ok(*c),
+            None => Err(format!("Unknown case '{}'", name))
+        }
+    }
+
+    /// The name used for referring to the case in the command line
+    pub fn name(&self)->&str{
+        match self {
+            Case::Section1_1 => "case1",
+            Case::Section1_2WithControl => "case2_with_control",
+            Case::Section1_2WithoutControl => "case2_without_control",
+            Case::Section1_3Busy => "case3_busy",
+            Case::Section1_3NotBusy => "case3_not_busy"
+        }
+    }
+
+    pub fn has_control(&self)->bool{
+        match self {
+            Case::Section1_1 => true,
+            Case::Section1_2WithControl => true,
+            Case::Section1_2WithoutControl => false,
+            Case::Section1_3Busy => false,
+            Case::Section1_3NotBusy => false
+        }
+    }
+
+    pub fn is_busy(&self)->bool {
+        match self{
+            Case::Section1_1 => false,
+            Case::Section1_2WithControl => false,
+            Case::Section1_2WithoutControl => false,
+            Case::Section1_3Busy => true,
+            Case::Section1_3NotBusy => false
+        }
+    }
+
+    pub fn is_proactive(&self)->bool{
+        match self {
+            Case::Section1_1 => true,
+            Case::Section1_2WithControl => false,
+            Case::Section1_2WithoutControl => false,
+            Case::Section1_3Busy => true,
+            Case::Section1_3NotBusy => true
+        }
+    }
+
+    pub fn filename(&self)->&str{
+        match self {
+            Case::Section1_1 => "Section1_1",
+            Case::Section1_2WithControl => "Section1_2WithControl",
+            Case::Section1_2WithoutControl => "Section1_2WithoutControl",
+            Case::Section1_3Busy => "Section1_3Busy",
+            Case::Section1_3NotBusy => "Section1_3NotBusy"
+        }
+    }
+}
+
+/// The parameters defining the behaviour of the person
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OccupantParameters {
+    /// Constant proactivity, between 0 and 1
+    pub proactivity: f64,
+
+    /// Constant busyness
+    pub busyness: f64,
+
+    /// Constant awareness of the future, in hours
+    pub awareness_of_the_future: f64,
+
+    /// Start and end of the sleeping hours. If `None`, the
+    /// defaults of the person are kept.
+    pub sleeping_hours: Option<[f64;2]>,
+
+    /* Coefficients of the perception polynomials */
+
+    /// Quadratic coefficient of the cold thermal sensation
+    pub thermal_sensation_cold: f64,
+
+    /// Quadratic coefficient of the hot thermal sensation
+    pub thermal_sensation_hot: f64,
+
+    /// Quadratic coefficient of the annoyance of too much clothing
+    pub clothing_too_much: f64,
+
+    /// Quadratic coefficient of the annoyance of too little clothing
+    pub clothing_too_little: f64,
+
+    /// Quadratic coefficient of too much loudness
+    pub loudness_too_much: f64,
+
+    /// Quadratic coefficient of too little loudness
+    pub loudness_too_little: f64,
+
+    /// Linear coefficient of brightness
+    pub brightness: f64,
+
+    /// Quadratic coefficient of utility bills
+    pub utility_bills: f64,
+}
+
+/// Default sleeping hours, used when only one end of them is set
+const DEFAULT_SLEEPING_HOURS : [f64;2] = [22.5, 6.5];
+
+impl OccupantParameters {
+
+    /// The parameters used in each of the thesis cases
+    pub fn for_case(case: Case)->Self{
+        OccupantParameters{
+            // Constant proactivity.
+            proactivity: if case.is_proactive(){ 0.99 }else{ 0.0 },
+
+            // Constant busyness
+            busyness: if case.is_busy(){ 24.0 }else{ 0.0 },
+
+            // Constant awareness of the future, 3 hours
+            awareness_of_the_future: 3.,
+
+            sleeping_hours: if case.is_busy(){ Some(DEFAULT_SLEEPING_HOURS) }else{ None },
+
+            // These are arbitrary (for now) and they only respect 
+            // the signs (e.g. good vs bad percepcions)
+            thermal_sensation_cold: -2.,
+            thermal_sensation_hot: -2.,
+            clothing_too_much: -1.5,
+            clothing_too_little: -1.5,
+            loudness_too_much: -2.,
+            loudness_too_little: -2.,
+            brightness: 5.0,
+            utility_bills: -0.1,
+        }
+    }
+
+    /// The names of the parameters accepted by `set`
+    pub fn parameter_names()->&'static [&'static str]{
+        &[
+            "proactivity",
+            "busyness",
+            "awareness_of_the_future",
+            "sleep_start",
+            "sleep_end",
+            "thermal_sensation_cold",
+            "thermal_sensation_hot",
+            "clothing_too_much",
+            "clothing_too_little",
+            "loudness_too_much",
+            "loudness_too_little",
+            "brightness",
+            "utility_bills",
+        ]
+    }
+
+    /// Sets a parameter by name
+    pub fn set(&mut self, name: &str, value: f64)->Result<(),String>{
+        match name {
+            "proactivity" => self.proactivity = value,
+            "busyness" => self.busyness = value,
+            "awareness_of_the_future" => self.awareness_of_the_future = value,
+            "sleep_start" => {
+                let end = self.sleeping_hours.unwrap_or(DEFAULT_SLEEPING_HOURS)[1];
+                self.sleeping_hours = Some([value, end]);
+            },
+            "sleep_end" => {
+                let start = self.sleeping_hours.unwrap_or(DEFAULT_SLEEPING_HOURS)[0];
+                self.sleeping_hours = Some([start, value]);
+            },
+            "thermal_sensation_cold" => self.thermal_sensation_cold = value,
+            "thermal_sensation_hot" => self.thermal_sensation_hot = value,
+            "clothing_too_much" => self.clothing_too_much = value,
+            "clothing_too_little" => self.clothing_too_little = value,
+            "loudness_too_much" => self.loudness_too_much = value,
+            "loudness_too_little" => self.loudness_too_little = value,
+            "brightness" => self.brightness = value,
+            "utility_bills" => self.utility_bills = value,
+            _ => return Err(format!("Unknown occupant parameter '{}'", name))
+        }
+        Ok(())
+    }
+}
+
+/// The geometry of a space of the building
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpaceGeometry {
+    pub name: String,
+
+    /// In m3
+    pub volume: f64,
+
+    /// Area of the opaque part of the exterior wall, in m2
+    pub wall_area: f64,
+
+    /// In m2
+    pub window_area: f64,
+}
+
+/// A case, with the parameters of its occupant
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CaseDefinition {
+    pub case: Case,
+    pub occupant: OccupantParameters,
+    pub building: BuildingParameters,
+
+    /// How the simulation is driven
+    #[serde(default)]
+    pub options: RunOptions,
+
+    /// What is calculated from the results, after simulating
+    #[serde(default)]
+    pub reports: ReportOptions,
+}
+
+impl CaseDefinition {
+    /// The definition used in the thesis
+    pub fn new(case: Case)->Self{
+        CaseDefinition{
+            case,
+            occupant: OccupantParameters::for_case(case),
+            building: BuildingParameters::default(),
+            options: RunOptions::default(),
+            reports: ReportOptions::default(),
+        }
+    }
+
+    /// The names of the parameters accepted by `set`
+    pub fn parameter_names()->Vec<&'static str>{
+        let mut ret = OccupantParameters::parameter_names().to_vec();
+        ret.extend_from_slice(BuildingParameters::parameter_names());
+        ret
+    }
+
+    /// Checks that a set of parameters can be set together, in any order.
+    /// `window_scale` sets the width of every window, so it cannot be
+    /// combined with the widths of individual windows.
+    pub fn check_parameters(names: &[&str])->Result<(),String>{
+        if names.contains(&"window_scale") {
+            if let Some(name) = names.iter().find(|n| n.ends_with("_window_width")) {
+                return Err(format!("'window_scale' cannot be combined with '{}', as it sets the width of every window", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets an occupant or building parameter by name
+    pub fn set(&mut self, name: &str, value: f64)->Result<(),String>{
+        if OccupantParameters::parameter_names().contains(&name) {
+            self.occupant.set(name, value)
+        }else if BuildingParameters::parameter_names().contains(&name) {
+            self.building.set(name, value)
+        }else{
+            Err(format!("Unknown parameter '{}'", name))
+        }
+    }
+}
+
+/// Creates the person that inhabits the building in a certain case
+pub fn create_person(case: Case, parameters: &OccupantParameters, state: &mut SimulationState)->Result<Person,String>{
+    let mut person = if case.has_control() {
+        Person::new(state)
+    }else{
+        Person::with_fixed_clothing(state, 1.0)        
+    };
+    if let Some([start, end]) = parameters.sleeping_hours {
+        person.set_sleeping_hours(start, end);
+    }
+
+    person.set_proactivity(Box::new(ScheduleConstant::new(parameters.proactivity)))?;
+    person.set_busyness(Box::new(ScheduleConstant::new(parameters.busyness)))?;
+
+    let awareness = ScheduleConstant::new(parameters.awareness_of_the_future * 3600.);
+    person.set_awareness_of_the_future(Box::new(awareness))?;
+    
+    // Add perceptions that are relevant to the person. These are polynomials 
+    // representing how different perceptions affect the person's immediate 
+    // satisfaction with the space.
+
+    // Cold and hot thermal sensations -> 0 + 0*x + a*x^2
+    person.add_perception( poly![0.0, 0.0, parameters.thermal_sensation_cold], Perception::ThermalSensationCold);    
+    person.add_perception( poly![0.0, 0.0, parameters.thermal_sensation_hot], Perception::ThermalSensationHot);    
+
+    // Too much and too little clothing -> 0 + 0*x + a*x^2
+    person.add_perception( poly![0.0, 0.0, parameters.clothing_too_much], Perception::ClothingAnnoyanceTooMuch);
+    person.add_perception( poly![0.0, 0.0, parameters.clothing_too_little], Perception::ClothingAnnoyanceTooLittle);    
+
+    // Too much and too little Loudness -> 0 + 0*x + a*x^2
+    person.add_perception( poly![0.0, 0.0, parameters.loudness_too_much], Perception::LoudnessTooMuch);
+    person.add_perception( poly![0.0, 0.0, parameters.loudness_too_little], Perception::LoudnessTooLittle);
+
+    // Brightness is good (more is better) -> 0 + a*x
+    person.add_perception( poly![0.0, parameters.brightness], Perception::Brightness);
+
+    // Utility bills are bad... -> 0 + 0*x + a*x^2
+    person.add_perception( poly![0.0, 0.0, parameters.utility_bills], Perception::UtilityBills);
+
+    Ok(person)
+}
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::csv_export::{column_name, write_file, CsvDialect};
+use crate::metering::timestep_energy;
+use crate::results_json::*;
+
+/// The quantities that are compared between result sets
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+    CurrentComfort,
+    PotentialComfort,
+    Satisfaction,
+    Energy,
+    Actions,
+}
+
+impl Metric {
+    pub fn all() -> [Metric; 5] {
+        [
+            Metric::CurrentComfort,
+            Metric::PotentialComfort,
+            Metric::Satisfaction,
+            Metric::Energy,
+            Metric::Actions,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::CurrentComfort => "Current comfort",
+            Metric::PotentialComfort => "Potential comfort",
+            Metric::Satisfaction => "Satisfaction",
+            Metric::Energy => "Energy",
+            Metric::Actions => "Actions",
+        }
+    }
+
+    pub fn units(&self) -> &'static str {
+        match self {
+            Metric::Energy => "kWh",
+            _ => "-",
+        }
+    }
+
+    /// Energy and actions are summarized as totals; the rest as means
+    fn is_cumulative(&self) -> bool {
+        match self {
+            Metric::Energy | Metric::Actions => true,
+            _ => false,
+        }
+    }
+}
+
+/// The values of the compared quantities in a single timestep. Comfort
+/// and satisfaction are only available when the person attended the building.
+#[derive(Clone, Debug)]
+pub struct TimestepRecord {
+    pub date: Date,
+    pub current_comfort: Option<f64>,
+    pub potential_comfort: Option<f64>,
+    pub satisfaction: Option<f64>,
+
+    /// Energy consumed during the timestep, in kWh
+    pub energy: f64,
+
+    /// Number of actions taken during the timestep
+    pub actions: usize,
+}
+
+impl TimestepRecord {
+    pub fn get(&self, metric: Metric) -> Option<f64> {
+        match metric {
+            Metric::CurrentComfort => self.current_comfort,
+            Metric::PotentialComfort => self.potential_comfort,
+            Metric::Satisfaction => self.satisfaction,
+            Metric::Energy => Some(self.energy),
+            Metric::Actions => Some(self.actions as f64),
+        }
+    }
+}
+
+/// The results of one simulation run, reduced to the compared quantities
+#[derive(Clone, Debug)]
+pub struct ResultSet {
+    pub name: String,
+    pub records: Vec<TimestepRecord>,
+}
+
+/// A key that can be used for matching dates
+fn date_key(date: &Date) -> (usize, usize, u64) {
+    (date.month, date.day, (date.hour * 3600.).round() as u64)
+}
+
+impl ResultSet {
+    /// Reads the JSON representation of some `SimulationResults`. The timestep
+    /// length is inferred from the first two timesteps.
+    pub fn from_json(name: &str, data: &Value) -> Result<Self, String> {
+        let data = timesteps(data)?;
+        if data.len() < 2 {
+            return Err(format!("Results '{}' need at least two timesteps", name));
+        }
+
+        let first = timestep_start(&data[0])?;
+        let second = timestep_start(&data[1])?;
+        if first.month != second.month || first.day != second.day || second.hour <= first.hour {
+            return Err(format!("Could not infer the timestep length of results '{}'", name));
+        }
+        let dt = second.hour - first.hour;
+
+        let mut records = Vec::with_capacity(data.len());
+        for tstep in data {
+            let person = controller(tstep, "person")?;
+            let attended = get_bool(person, "attended")?;
+            let when_attended = |field: &str| -> Result<Option<f64>, String> {
+                if attended {
+                    Ok(Some(get_f64(person, field)?))
+                } else {
+                    Ok(None)
+                }
+            };
+
+            records.push(TimestepRecord {
+                date: timestep_start(tstep)?,
+                current_comfort: when_attended("current_comfort")?,
+                potential_comfort: when_attended("potential_comfort")?,
+                satisfaction: when_attended("dwelling_satisfaction_before")?,
+                energy: timestep_energy(tstep, dt),
+                actions: if attended { actions_taken(person)?.len() } else { 0 },
+            });
+        }
+
+        Ok(ResultSet {
+            name: name.to_string(),
+            records,
+        })
+    }
+
+    /// Reads a JSON file written after a simulation run. The file
+    /// name (without extension) is used as the name of the set.
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        let data: Value = match serde_json::from_str(&data) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not parse file '{}': {}", filename, e)),
+        };
+        let name = std::path::Path::new(filename)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| filename.to_string());
+        Self::from_json(&name, &data)
+    }
+}
+
+/// The summary of the difference between an alternative and the baseline
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MetricSummary {
+    pub metric: Metric,
+
+    /// The name of the alternative
+    pub alternative: String,
+
+    /// The mean (or total, for energy and actions) in the baseline,
+    /// over the compared timesteps
+    pub baseline_value: f64,
+
+    /// The mean (or total, for energy and actions) in the alternative,
+    /// over the compared timesteps
+    pub alternative_value: f64,
+
+    /// Number of aligned timesteps in which the metric was available in
+    /// both. When it is 0, the other values are NaN.
+    pub n_compared: usize,
+
+    /// Mean of the timestep deltas (alternative - baseline)
+    pub mean_delta: f64,
+
+    /// Largest absolute timestep delta
+    pub max_abs_delta: f64,
+
+    /// Root mean square of the timestep deltas
+    pub rms_delta: f64,
+}
+
+/// A comparison between a baseline and one or more alternatives,
+/// aligned by timestep
+pub struct Comparison {
+    pub baseline: ResultSet,
+    pub alternatives: Vec<ResultSet>,
+
+    /// For each alternative, the index of the record matching each of the
+    /// baseline records (if any)
+    alignment: Vec<Vec<Option<usize>>>,
+}
+
+impl Comparison {
+    /// Compares result sets. The first one is the baseline.
+    pub fn new(mut sets: Vec<ResultSet>) -> Result<Self, String> {
+        if sets.len() < 2 {
+            return Err("At least two result sets are needed for a comparison".to_string());
+        }
+        let baseline = sets.remove(0);
+
+        let alignment = sets
+            .iter()
+            .map(|alt| {
+                let index: HashMap<(usize, usize, u64), usize> = alt
+                    .records
+                    .iter()
+                    .enumerate()
+                    .map(|(i, r)| (date_key(&r.date), i))
+                    .collect();
+                baseline
+                    .records
+                    .iter()
+                    .map(|r| index.get(&date_key(&r.date)).cloned())
+                    .collect()
+            })
+            .collect();
+
+        Ok(Comparison {
+            baseline,
+            alternatives: sets,
+            alignment,
+        })
+    }
+
+    /// The deltas (alternative - baseline) of a metric in each
+    /// timestep of the baseline
+    pub fn deltas(&self, alternative: usize, metric: Metric) -> Vec<(Date, Option<f64>)> {
+        let alt = &self.alternatives[alternative];
+        self.baseline
+            .records
+            .iter()
+            .zip(self.alignment[alternative].iter())
+            .map(|(base, i)| {
+                let delta = match (base.get(metric), i.and_then(|i| alt.records[i].get(metric))) {
+                    (Some(b), Some(a)) => Some(a - b),
+                    _ => None,
+                };
+                (base.date, delta)
+            })
+            .collect()
+    }
+
+    /// The `(baseline, alternative)` values of a metric in the aligned
+    /// timesteps where it is available in both
+    fn aligned_values(&self, alternative: usize, metric: Metric) -> Vec<(f64, f64)> {
+        let alt = &self.alternatives[alternative];
+        self.baseline
+            .records
+            .iter()
+            .zip(self.alignment[alternative].iter())
+            .filter_map(|(base, i)| Some((base.get(metric)?, alt.records[(*i)?].get(metric)?)))
+            .collect()
+    }
+
+    /// Summarizes the differences of all metrics, for every alternative.
+    /// Only the aligned timesteps are compared, so sets covering different
+    /// periods are summarized over the period they share.
+    pub fn summary(&self) -> Vec<MetricSummary> {
+        let mut ret = Vec::new();
+        for (alt_index, alt) in self.alternatives.iter().enumerate() {
+            for metric in Metric::all().iter() {
+                let pairs = self.aligned_values(alt_index, *metric);
+                let n = pairs.len();
+                if n == 0 {
+                    ret.push(MetricSummary {
+                        metric: *metric,
+                        alternative: alt.name.clone(),
+                        baseline_value: std::f64::NAN,
+                        alternative_value: std::f64::NAN,
+                        n_compared: 0,
+                        mean_delta: std::f64::NAN,
+                        max_abs_delta: std::f64::NAN,
+                        rms_delta: std::f64::NAN,
+                    });
+                    continue;
+                }
+
+                let aggregate = |values: Vec<f64>| -> f64 {
+                    if metric.is_cumulative() {
+                        values.iter().sum()
+                    } else {
+                        crate::statistics::mean(&values)
+                    }
+                };
+                let deltas: Vec<f64> = pairs.iter().map(|(b, a)| a - b).collect();
+                ret.push(MetricSummary {
+                    metric: *metric,
+                    alternative: alt.name.clone(),
+                    baseline_value: aggregate(pairs.iter().map(|(b, _)| *b).collect()),
+                    alternative_value: aggregate(pairs.iter().map(|(_, a)| *a).collect()),
+                    n_compared: n,
+                    mean_delta: deltas.iter().sum::<f64>() / n as f64,
+                    max_abs_delta: deltas.iter().fold(0.0, |a, b| a.max(b.abs())),
+                    rms_delta: (deltas.iter().map(|d| d * d).sum::<f64>() / n as f64).sqrt(),
+                });
+            }
+        }
+        ret
+    }
+
+    /// Writes the timestep deltas of every metric and alternative
+    pub fn write_deltas_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header = vec!["Timestamp".to_string()];
+        let mut columns = Vec::new();
+        for (alt_index, alt) in self.alternatives.iter().enumerate() {
+            for metric in Metric::all().iter() {
+                header.push(dialect.text(&column_name(
+                    &format!("Delta {} - {} vs {}", metric.name(), alt.name, self.baseline.name),
+                    metric.units(),
+                )));
+                columns.push(self.deltas(alt_index, *metric));
+            }
+        }
+
+        let rows: Vec<Vec<String>> = self
+            .baseline
+            .records
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let mut row = vec![dialect.timestamp(&r.date)];
+                for c in columns.iter() {
+                    row.push(c[i].1.map(|v| dialect.number(v)).unwrap_or_default());
+                }
+                row
+            })
+            .collect();
+
+        write_file(filename, &header, &rows, dialect)
+    }
+
+    /// Writes the summary statistics of the comparison
+    pub fn write_summary_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let header: Vec<String> = [
+            "Metric",
+            "Units",
+            "Alternative",
+            "Baseline",
+            "Alternative value",
+            "Compared timesteps",
+            "Mean delta",
+            "Max. abs. delta",
+            "RMS delta",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let rows: Vec<Vec<String>> = self
+            .summary()
+            .iter()
+            .map(|s| {
+                // Left empty when nothing could be compared
+                let number = |v: f64| if s.n_compared > 0 { dialect.number(v) } else { String::new() };
+                vec![
+                    s.metric.name().to_string(),
+                    s.metric.units().to_string(),
+                    dialect.text(&s.alternative),
+                    number(s.baseline_value),
+                    number(s.alternative_value),
+                    format!("{}", s.n_compared),
+                    number(s.mean_delta),
+                    number(s.max_abs_delta),
+                    number(s.rms_delta),
+                ]
+            })
+            .collect();
+
+        write_file(filename, &header, &rows, dialect)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Baseline: {}", self.baseline.name)?;
+        writeln!(
+            f,
+            "{:<20} {:<6} {:<30} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            "Metric", "Units", "Alternative", "Baseline", "Alternative", "Mean delta", "Max |delta|", "RMS delta"
+        )?;
+        for s in self.summary() {
+            writeln!(
+                f,
+                "{:<20} {:<6} {:<30} {:>12.3} {:>12.3} {:>12.3} {:>12.3} {:>12.3}",
+                s.metric.name(),
+                s.metric.units(),
+                s.alternative,
+                s.baseline_value,
+                s.alternative_value,
+                s.mean_delta,
+                s.max_abs_delta,
+                s.rms_delta
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use weather::Weather;
+
+use crate::case::{Case, CaseDefinition};
+use crate::csv_export::{write_file, CsvDialect};
+use crate::kpi::KpiSummary;
+use crate::results_json::timesteps;
+use crate::runner::{simulate_period, simulation_period};
+use crate::state_elements::{space_values, SPACE_DRY_BULB_TEMPERATURE};
+
+fn default_steps_per_hour() -> Vec<usize> {
+    vec![4, 6, 12, 30, 60, 120]
+}
+
+fn default_tolerance() -> f64 {
+    0.01
+}
+
+fn default_temperature_tolerance() -> f64 {
+    0.1
+}
+
+/// The configuration of a timestep convergence study, read from a JSON file such as
+///
+/// ```json
+/// {
+///     "case": "case1",
+///     "weather": "Santiago.epw",
+///     "steps_per_hour": [4, 6, 12, 30, 60, 120]
+/// }
+/// ```
+///
+/// The finest resolution is taken as the reference.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConvergenceConfig {
+    /// The name of the case (e.g. `case1`)
+    pub case: String,
+
+    /// The weather: an EPW file or a JSON weather specification
+    pub weather: String,
+
+    /// The resolutions simulated
+    #[serde(default = "default_steps_per_hour")]
+    pub steps_per_hour: Vec<usize>,
+
+    /// Relative change in comfort and energy, with respect to the
+    /// reference, below which a resolution is considered converged
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+
+    /// RMSE of the hourly space temperatures (in K), with respect
+    /// to the reference, below which a resolution is considered converged
+    #[serde(default = "default_temperature_tolerance")]
+    pub temperature_tolerance: f64,
+}
+
+impl ConvergenceConfig {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        match serde_json::from_str(&data) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(format!("Could not parse convergence configuration '{}': {}", filename, e)),
+        }
+    }
+}
+
+/// The outcome of simulating with a certain resolution
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConvergencePoint {
+    /// Timesteps per hour
+    pub n: usize,
+
+    /// Wall-clock time of the simulation, in seconds
+    pub wall_time: f64,
+
+    pub kpi: KpiSummary,
+
+    /// The temperature of every space at the start of every hour
+    pub hourly_temperatures: Vec<Vec<f64>>,
+
+    /// Relative change of the mean comfort with respect to the reference
+    pub comfort_change: f64,
+
+    /// Relative change of the total energy with respect to the reference
+    pub energy_change: f64,
+
+    /// Change in the number of actions with respect to the reference
+    pub actions_change: i64,
+
+    /// RMSE of the hourly temperatures with respect to the reference, in K
+    pub temperature_rmse: f64,
+
+    /// Whether this resolution is within the tolerances
+    pub converged: bool,
+}
+
+/// The results of a timestep convergence study
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConvergenceResults {
+    pub config: ConvergenceConfig,
+
+    /// The simulated resolutions, from coarsest to finest
+    pub points: Vec<ConvergencePoint>,
+}
+
+/// The temperature of every space (sorted by index) at the start of every hour
+fn hourly_temperatures(data: &Value, n: usize) -> Result<Vec<Vec<f64>>, String> {
+    Ok(timesteps(data)?
+        .iter()
+        .step_by(n)
+        .map(|tstep| {
+            let mut temps = space_values(&tstep["state_elements"], SPACE_DRY_BULB_TEMPERATURE);
+            temps.sort_by_key(|(i, _)| *i);
+            temps.into_iter().map(|(_, v)| v).collect()
+        })
+        .collect())
+}
+
+/// The change of `value` relative to `reference`
+fn relative_change(value: f64, reference: f64) -> f64 {
+    if reference.abs() > 1e-9 {
+        (value - reference) / reference.abs()
+    } else {
+        value - reference
+    }
+}
+
+/// The root mean square difference of two sets of hourly temperatures
+fn rmse(a: &[Vec<f64>], b: &[Vec<f64>]) -> f64 {
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (a, b) in a.iter().zip(b.iter()) {
+        for (a, b) in a.iter().zip(b.iter()) {
+            sum += (a - b).powi(2);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return 0.0;
+    }
+    (sum / count as f64).sqrt()
+}
+
+/// Simulates a case with each of the resolutions of the study.
+///
+/// The runs are sequential, so that their wall-clock times are comparable.
+pub fn run_convergence(config: &ConvergenceConfig, weather: &dyn Weather) -> Result<ConvergenceResults, String> {
+    let case = Case::from_name(&config.case)?;
+    let mut resolutions = config.steps_per_hour.clone();
+    resolutions.sort_unstable();
+    resolutions.dedup();
+    if resolutions.len() < 2 {
+        return Err("A convergence study needs at least two resolutions".to_string());
+    }
+    if resolutions[0] == 0 {
+        return Err("Timesteps per hour must be greater than zero".to_string());
+    }
+
+    let definition = CaseDefinition::new(case);
+    let mut runs = Vec::with_capacity(resolutions.len());
+    for n in resolutions {
+        let simulation = simulate_period(&definition, weather, simulation_period(), n)?;
+        let kpi = simulation.kpi()?;
+        let temperatures = hourly_temperatures(&simulation.results, n)?;
+        runs.push((n, simulation.metadata.wall_time, kpi, temperatures));
+    }
+
+    let (_, _, reference, reference_temperatures) = runs.last().unwrap().clone();
+    let reference_comfort = reference.current_comfort.mean;
+    let reference_energy = reference.heating_energy() + reference.lighting_energy();
+
+    let points = runs
+        .into_iter()
+        .map(|(n, wall_time, kpi, hourly_temperatures)| {
+            let comfort_change = relative_change(kpi.current_comfort.mean, reference_comfort);
+            let energy_change = relative_change(kpi.heating_energy() + kpi.lighting_energy(), reference_energy);
+            let temperature_rmse = rmse(&hourly_temperatures, &reference_temperatures);
+            let converged = comfort_change.abs() <= config.tolerance
+                && energy_change.abs() <= config.tolerance
+                && temperature_rmse <= config.temperature_tolerance;
+            ConvergencePoint {
+                n,
+                wall_time,
+                actions_change: kpi.total_actions() as i64 - reference.total_actions() as i64,
+                kpi,
+                hourly_temperatures,
+                comfort_change,
+                energy_change,
+                temperature_rmse,
+                converged,
+            }
+        })
+        .collect();
+
+    Ok(ConvergenceResults {
+        config: config.clone(),
+        points,
+    })
+}
+
+impl ConvergenceResults {
+    /// The coarsest resolution that, like every finer one,
+    /// is within the tolerances of the reference
+    pub fn cheapest_converged(&self) -> Option<&ConvergencePoint> {
+        let unconverged = self.points.iter().rposition(|p| !p.converged);
+        match unconverged {
+            Some(i) => self.points.get(i + 1),
+            None => self.points.first(),
+        }
+    }
+
+    /// Writes a table with one row per resolution
+    pub fn write_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header: Vec<String> = vec!["steps_per_hour".to_string(), "wall_time".to_string()];
+        header.extend(KpiSummary::names().iter().map(|s| s.to_string()));
+        for name in ["comfort_change", "energy_change", "actions_change", "temperature_rmse", "converged"].iter() {
+            header.push(name.to_string());
+        }
+
+        let rows: Vec<Vec<String>> = self
+            .points
+            .iter()
+            .map(|p| {
+                let mut row = vec![format!("{}", p.n), dialect.number(p.wall_time)];
+                for name in KpiSummary::names() {
+                    row.push(dialect.number(p.kpi.get(name).unwrap_or(std::f64::NAN)));
+                }
+                row.push(dialect.number(p.comfort_change));
+                row.push(dialect.number(p.energy_change));
+                row.push(format!("{}", p.actions_change));
+                row.push(dialect.number(p.temperature_rmse));
+                row.push(format!("{}", p.converged));
+                row
+            })
+            .collect();
+
+        write_file(filename, &header, &rows, dialect)
+    }
+
+    /// Writes the whole study as JSON
+    pub fn write_json(&self, filename: &str) -> Result<(), String> {
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize convergence results: {}", e)),
+        };
+        match std::fs::write(filename, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+        }
+    }
+}
+
+impl fmt::Display for ConvergenceResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>6} {:>12} {:>12} {:>10} {:>12} {:>10} {:>10} {:>10} {:>10}",
+            "n", "Time [s]", "Comfort [-]", "Change", "Energy [kWh]", "Change", "Actions", "RMSE [K]", "Converged"
+        )?;
+        for p in self.points.iter() {
+            writeln!(
+                f,
+                "{:>6} {:>12.2} {:>12.4} {:>9.2}% {:>12.3} {:>9.2}% {:>10} {:>10.3} {:>10}",
+                p.n,
+                p.wall_time,
+                p.kpi.current_comfort.mean,
+                100. * p.comfort_change,
+                p.kpi.heating_energy() + p.kpi.lighting_energy(),
+                100. * p.energy_change,
+                p.kpi.total_actions(),
+                p.temperature_rmse,
+                if p.converged { "yes" } else { "no" }
+            )?;
+        }
+        match self.cheapest_converged() {
+            Some(p) if p.n != self.points.last().unwrap().n => writeln!(
+                f,
+                "Cheapest converged resolution: {} steps per hour ({:.2} s)",
+                p.n, p.wall_time
+            ),
+            _ => writeln!(f, "No resolution coarser than the reference is converged"),
+        }
+    }
+}
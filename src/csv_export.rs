@@ -0,0 +1,227 @@
+use std::io::Write;
+
+use building_model::building::Building;
+use calendar::date::Date;
+use serde_json::Value;
+
+use crate::results_json::*;
+
+/// The format of the CSV files exported by this crate.
+#[derive(Clone, Copy, Debug)]
+pub struct CsvDialect {
+    /// The character separating fields
+    pub delimiter: char,
+
+    /// The character separating the integer and decimal
+    /// parts of numbers
+    pub decimal_separator: char,
+
+    /// The year written in the timestamps. `Date` does not
+    /// have a year, so this is just nominal.
+    pub year: usize,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        CsvDialect {
+            delimiter: ',',
+            decimal_separator: '.',
+            year: 2021,
+        }
+    }
+}
+
+impl CsvDialect {
+    /// Builds a new dialect, checking that numbers will
+    /// not be confused with separators
+    pub fn new(delimiter: char, decimal_separator: char) -> Result<Self, String> {
+        if delimiter == decimal_separator {
+            return Err(format!(
+                "CSV delimiter and decimal separator cannot be the same ('{}')",
+                delimiter
+            ));
+        }
+        Ok(CsvDialect {
+            delimiter,
+            decimal_separator,
+            ..CsvDialect::default()
+        })
+    }
+
+    /// Formats a number
+    pub fn number(&self, v: f64) -> String {
+        let s = format!("{}", v);
+        if self.decimal_separator == '.' {
+            s
+        } else {
+            s.replace('.', &self.decimal_separator.to_string())
+        }
+    }
+
+    /// Formats a piece of text, quoting it if needed
+    pub fn text(&self, s: &str) -> String {
+        if s.contains(self.delimiter) || s.contains('"') || s.contains('\n') {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        }
+    }
+
+    /// Formats a `Date` as an ISO 8601 timestamp
+    pub fn timestamp(&self, date: &Date) -> String {
+        iso8601(date, self.year)
+    }
+
+    /// Joins fields into a row, including the line break
+    pub fn row(&self, fields: &[String]) -> String {
+        let mut ret = fields.join(&self.delimiter.to_string());
+        ret.push('\n');
+        ret
+    }
+}
+
+/// Formats a `Date` as an ISO 8601 timestamp (e.g. `2021-07-01T13:30:00`)
+pub fn iso8601(date: &Date, year: usize) -> String {
+    let seconds = (date.hour * 3600.).round() as usize;
+    let h = seconds / 3600;
+    let m = (seconds % 3600) / 60;
+    let s = seconds % 60;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year, date.month, date.day, h, m, s
+    )
+}
+
+/// Builds the name of a column, annotated with its units (e.g. `Temperature [C]`)
+pub fn column_name(name: &str, units: &str) -> String {
+    format!("{} [{}]", name, units)
+}
+
+/// Writes a CSV file
+pub fn write_file(filename: &str, header: &[String], rows: &[Vec<String>], dialect: &CsvDialect) -> Result<(), String> {
+    let mut file = match std::fs::File::create(filename) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Could not create file '{}': {}", filename, e)),
+    };
+
+    let mut content = dialect.row(header);
+    for row in rows {
+        content += &dialect.row(row);
+    }
+
+    match file.write_all(content.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+    }
+}
+
+/// Writes the comfort of the person, the status of each of their perceptions
+/// (one column per perception and space) and the actions they took, for
+/// every timestep in which they attended the building
+pub fn write_operation(filename: &str, building: &Building, data: &Value, dialect: &CsvDialect) -> Result<(), String> {
+    let data = timesteps(data)?;
+
+    // Columns are named from the first available current_status
+    let mut status_columns: Vec<(String, String)> = Vec::new();
+    for tstep in data {
+        let person = controller(tstep, "person")?;
+        if get_bool(person, "attended")? {
+            status_columns = current_status(person)?
+                .into_iter()
+                .map(|(perception, space, _)| (perception, space))
+                .collect();
+            break;
+        }
+    }
+
+    let mut header = vec!["Timestamp".to_string(), column_name("Comfort", "-")];
+    for (perception, space) in status_columns.iter() {
+        header.push(dialect.text(&column_name(&format!("{} - {}", perception, space), "-")));
+    }
+    header.push("Perception to fix".to_string());
+    header.push("Location of perception to fix".to_string());
+    header.push("Actions taken".to_string());
+
+    let mut rows = Vec::with_capacity(data.len());
+    for tstep in data {
+        let date = timestep_start(tstep)?;
+        let person = controller(tstep, "person")?;
+
+        if !get_bool(person, "attended")? {
+            continue;
+        }
+
+        let perception_to_fix = get_str(person, "perception_to_fix")?;
+        let location_index = get_f64(person, "location_of_worst_perception")? as usize;
+        let location_to_fix = building.get_space(location_index)?.name();
+        let comfort = get_f64(person, "potential_comfort")?;
+
+        let mut row = vec![dialect.timestamp(&date), dialect.number(comfort)];
+
+        // Register perceptions
+        let status = current_status(person)?;
+        for (perception, space) in status_columns.iter() {
+            let value = status
+                .iter()
+                .find(|(p, s, _)| p == perception && s == space)
+                .map(|(_, _, v)| dialect.number(*v));
+            row.push(value.unwrap_or_default());
+        }
+
+        // Register actions
+        let actions = actions_taken(person)?;
+        let actions = if actions.is_empty() {
+            "None".to_string()
+        } else {
+            actions
+                .iter()
+                .map(|(action, loc)| format!("{} in {}", action, loc))
+                .collect::<Vec<String>>()
+                .join(" | ")
+        };
+
+        row.push(dialect.text(perception_to_fix));
+        row.push(dialect.text(location_to_fix));
+        row.push(dialect.text(&actions));
+        rows.push(row);
+    }
+
+    write_file(filename, &header, &rows, dialect)
+}
+
+/// Writes the actual and potential comfort of the person, as well as
+/// their satisfaction with the dwelling, for every timestep in which
+/// they attended the building
+pub fn write_comfort(filename: &str, data: &Value, dialect: &CsvDialect) -> Result<(), String> {
+    let data = timesteps(data)?;
+
+    let header = vec![
+        "Timestamp".to_string(),
+        column_name("Actual comfort", "-"),
+        column_name("Potential comfort", "-"),
+        column_name("Satisfaction", "-"),
+    ];
+
+    let mut rows = Vec::with_capacity(data.len());
+    for tstep in data {
+        let date = timestep_start(tstep)?;
+        let person = controller(tstep, "person")?;
+
+        if !get_bool(person, "attended")? {
+            continue;
+        }
+
+        let actual_comfort = get_f64(person, "current_comfort")?;
+        let potential_comfort = get_f64(person, "potential_comfort")?;
+        let satisfaction = get_f64(person, "dwelling_satisfaction_before")?;
+
+        rows.push(vec![
+            dialect.timestamp(&date),
+            dialect.number(actual_comfort),
+            dialect.number(potential_comfort),
+            dialect.number(satisfaction),
+        ]);
+    }
+
+    write_file(filename, &header, &rows, dialect)
+}
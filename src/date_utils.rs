@@ -0,0 +1,44 @@
+use calendar::date::Date;
+
+/// Number of days in each month (weather years have no leap day)
+const DAYS_IN_MONTH: [usize; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// Number of days in a month (1 to 12)
+pub fn days_in_month(month: usize) -> usize {
+    DAYS_IN_MONTH[(month - 1) % 12]
+}
+
+/// Day of the year of a `Date`, starting from 1 on January 1st
+pub fn day_of_year(date: &Date) -> usize {
+    DAYS_IN_MONTH[..date.month - 1].iter().sum::<usize>() + date.day
+}
+
+/// Hours elapsed since the beginning of the year
+pub fn hour_of_year(date: &Date) -> f64 {
+    (day_of_year(date) - 1) as f64 * 24. + date.hour
+}
+
+/// The `Date` at a certain number of hours since the beginning
+/// of the year. Values beyond one year wrap around.
+pub fn date_from_hour_of_year(hours: f64) -> Date {
+    let hours = hours.rem_euclid(8760.);
+    let mut day = (hours / 24.).floor() as usize;
+    let hour = hours - day as f64 * 24.;
+
+    let mut month = 1;
+    while day >= days_in_month(month) {
+        day -= days_in_month(month);
+        month += 1;
+    }
+    Date {
+        month,
+        day: day + 1,
+        hour,
+    }
+}
+
+/// Hours elapsed between two dates (negative if `to` is before `from`),
+/// assuming both are in the same year
+pub fn hours_between(from: &Date, to: &Date) -> f64 {
+    hour_of_year(to) - hour_of_year(from)
+}
@@ -0,0 +1,323 @@
+use std::fmt;
+
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::case::BuildingParameters;
+use crate::csv_export::{column_name, write_file, CsvDialect};
+use crate::interpolation::SolarLocation;
+use crate::results_json::{timestep_start, timesteps};
+use crate::state_elements::{
+    node_values, space_values, FENESTRATION_NODE_TEMPERATURE, SPACE_DRY_BULB_TEMPERATURE, SPACE_HEATING_COOLING_POWER,
+    SPACE_INFILTRATION_VOLUME, SPACE_LIGHTING_POWER, SPACE_VENTILATION_VOLUME, SURFACE_NODE_TEMPERATURE,
+};
+
+/// Density of air, in kg/m3
+const AIR_DENSITY: f64 = 1.2;
+
+/// Specific heat capacity of air, in J/kg.K
+const AIR_SPECIFIC_HEAT: f64 = 1005.;
+
+fn default_threshold() -> f64 {
+    50.
+}
+
+fn default_convection_coefficient() -> f64 {
+    3.
+}
+
+fn default_solar_transmittance() -> f64 {
+    0.85
+}
+
+/// The assumptions of the estimate of the heat balance of the spaces
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnergyBalanceConfig {
+    /// Residuals above this value (in W) are flagged
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+
+    /// Interior convective heat transfer coefficient assumed for
+    /// walls and windows, in W/m2.K
+    #[serde(default = "default_convection_coefficient")]
+    pub convection_coefficient: f64,
+
+    /// Fraction of the solar radiation reaching a window that is
+    /// transmitted into the space (0.85 for clear 3mm glass)
+    #[serde(default = "default_solar_transmittance")]
+    pub solar_transmittance: f64,
+}
+
+impl Default for EnergyBalanceConfig {
+    fn default() -> Self {
+        EnergyBalanceConfig {
+            threshold: default_threshold(),
+            convection_coefficient: default_convection_coefficient(),
+            solar_transmittance: default_solar_transmittance(),
+        }
+    }
+}
+
+/// The estimated heat balance of the air of a space during a timestep.
+/// Gains are positive, and every term is in W. Terms whose state elements
+/// (or, for the solar gains, location) are not available are `None`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BalanceRow {
+    /// The start of the timestep
+    pub date: String,
+    pub space: usize,
+    pub space_name: String,
+
+    /// Convection from the interior face of the exterior wall
+    pub wall_convection: Option<f64>,
+
+    /// Convection from the interior face of the window
+    pub window_convection: Option<f64>,
+
+    /// Solar radiation transmitted through the window
+    pub window_solar: Option<f64>,
+
+    pub heating: Option<f64>,
+    pub lighting: Option<f64>,
+
+    /// Outdoor air entering through infiltration and open windows
+    pub ventilation: Option<f64>,
+
+    /// Heat stored in the air of the space
+    pub storage: f64,
+
+    /// What the available terms do not explain
+    pub residual: f64,
+}
+
+impl BalanceRow {
+    /// The names of the terms that could not be estimated
+    pub fn missing(&self) -> Vec<&'static str> {
+        [
+            ("wall_convection", self.wall_convection),
+            ("window_convection", self.window_convection),
+            ("window_solar", self.window_solar),
+            ("heating", self.heating),
+            ("lighting", self.lighting),
+            ("ventilation", self.ventilation),
+        ]
+        .iter()
+        .filter(|(_, v)| v.is_none())
+        .map(|(name, _)| *name)
+        .collect()
+    }
+}
+
+/// An approximate heat balance of the air of every space and timestep,
+/// reconstructed after the simulation from the recorded state.
+///
+/// It is not the balance solved by the thermal model: convection uses an
+/// assumed coefficient instead of the one of the model, the solar gains use
+/// an assumed transmittance, and only the heat stored in the air is counted
+/// (the heat stored in walls reaches the air through convection). Large
+/// residuals point at timesteps worth checking, not necessarily at errors.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnergyBalanceEstimate {
+    pub config: EnergyBalanceConfig,
+    pub rows: Vec<BalanceRow>,
+}
+
+impl EnergyBalanceEstimate {
+    /// Estimates the balance of each space between the start of each
+    /// timestep and the next one, using the average of the fluxes at
+    /// both ends.
+    ///
+    /// The interior face of walls and windows is taken to be their node 0,
+    /// as the spaces are on their front side. Windows face up in the cases
+    /// (see `get_squared_polygon`), so they receive the global horizontal
+    /// radiation, which needs the `location` of the weather.
+    pub fn from_json(
+        data: &Value,
+        building: &BuildingParameters,
+        n: usize,
+        config: &EnergyBalanceConfig,
+        location: Option<&SolarLocation>,
+    ) -> Result<Self, String> {
+        let data = timesteps(data)?;
+        let dt = 3600. / n as f64;
+        let geometry = building.space_geometry();
+        let window_index = building.window_indices();
+
+        let mut rows = Vec::new();
+        for pair in data.windows(2) {
+            let (before, after) = (&pair[0], &pair[1]);
+            let dates = (timestep_start(before)?, timestep_start(after)?);
+            let date = format!("{}", dates.0);
+
+            let space_value = |tstep: &Value, variant: &str, space: usize| -> Option<f64> {
+                space_values(&tstep["state_elements"], variant)
+                    .into_iter()
+                    .find(|(i, _)| *i == space)
+                    .map(|(_, v)| v)
+            };
+            let inner_node = |tstep: &Value, variant: &str, object: usize| -> Option<f64> {
+                node_values(&tstep["state_elements"], variant)
+                    .into_iter()
+                    .find(|(i, node, _)| *i == object && *node == 0)
+                    .map(|(_, _, v)| v)
+            };
+            let weather = |tstep: &Value, field: &str| tstep["weather"][field].as_f64();
+
+            // The average of a term at both ends of the timestep, if known at both
+            let average = |f: &dyn Fn(&Value, &Date) -> Option<f64>| -> Option<f64> {
+                Some(0.5 * (f(before, &dates.0)? + f(after, &dates.1)?))
+            };
+
+            for (space, g) in geometry.iter().enumerate() {
+                let air = |tstep: &Value| space_value(tstep, SPACE_DRY_BULB_TEMPERATURE, space);
+                let (t0, t1) = match (air(before), air(after)) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => continue,
+                };
+                let h = config.convection_coefficient;
+
+                let wall_convection = average(&|tstep: &Value, _: &Date| {
+                    Some(h * g.wall_area * (inner_node(tstep, SURFACE_NODE_TEMPERATURE, space)? - air(tstep)?))
+                });
+                let (window_convection, window_solar) = match window_index[space] {
+                    Some(w) => (
+                        average(&|tstep: &Value, _: &Date| {
+                            Some(h * g.window_area * (inner_node(tstep, FENESTRATION_NODE_TEMPERATURE, w)? - air(tstep)?))
+                        }),
+                        average(&|tstep: &Value, date: &Date| {
+                            let cos_zenith = location?.cos_zenith(date).max(0.0);
+                            let global = weather(tstep, "direct_normal_radiation")? * cos_zenith
+                                + weather(tstep, "diffuse_horizontal_radiation")?;
+                            Some(config.solar_transmittance * g.window_area * global)
+                        }),
+                    ),
+                    // Nothing to gain through a window that does not exist
+                    None => (Some(0.0), Some(0.0)),
+                };
+                let heating = average(&|tstep: &Value, _: &Date| space_value(tstep, SPACE_HEATING_COOLING_POWER, space));
+                let lighting = average(&|tstep: &Value, _: &Date| space_value(tstep, SPACE_LIGHTING_POWER, space));
+                let ventilation = average(&|tstep: &Value, _: &Date| {
+                    let volume = space_value(tstep, SPACE_INFILTRATION_VOLUME, space)?
+                        + space_value(tstep, SPACE_VENTILATION_VOLUME, space)?;
+                    Some(AIR_DENSITY * AIR_SPECIFIC_HEAT * volume * (weather(tstep, "dry_bulb_temperature")? - air(tstep)?))
+                });
+                let storage = AIR_DENSITY * AIR_SPECIFIC_HEAT * g.volume * (t1 - t0) / dt;
+
+                let gains: f64 = [wall_convection, window_convection, window_solar, heating, lighting, ventilation]
+                    .iter()
+                    .filter_map(|v| *v)
+                    .sum();
+                rows.push(BalanceRow {
+                    date: date.clone(),
+                    space,
+                    space_name: g.name.clone(),
+                    wall_convection,
+                    window_convection,
+                    window_solar,
+                    heating,
+                    lighting,
+                    ventilation,
+                    storage,
+                    residual: gains - storage,
+                });
+            }
+        }
+
+        Ok(EnergyBalanceEstimate {
+            config: config.clone(),
+            rows,
+        })
+    }
+
+    /// The rows whose residual exceeds the threshold
+    pub fn flagged(&self) -> Vec<&BalanceRow> {
+        self.rows
+            .iter()
+            .filter(|r| r.residual.abs() > self.config.threshold)
+            .collect()
+    }
+
+    /// Writes one row per space and timestep, for plotting. Terms that
+    /// could not be estimated are left empty.
+    pub fn write_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header = vec!["Date".to_string(), "Space".to_string()];
+        for term in [
+            "Wall convection",
+            "Window convection",
+            "Window solar",
+            "Heating",
+            "Lighting",
+            "Ventilation",
+            "Storage",
+            "Residual",
+        ]
+        .iter()
+        {
+            header.push(column_name(term, "W"));
+        }
+        header.push("Flagged".to_string());
+
+        let term = |v: Option<f64>| v.map(|v| dialect.number(v)).unwrap_or_default();
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|r| {
+                vec![
+                    dialect.text(&r.date),
+                    dialect.text(&r.space_name),
+                    term(r.wall_convection),
+                    term(r.window_convection),
+                    term(r.window_solar),
+                    term(r.heating),
+                    term(r.lighting),
+                    term(r.ventilation),
+                    dialect.number(r.storage),
+                    dialect.number(r.residual),
+                    format!("{}", r.residual.abs() > self.config.threshold),
+                ]
+            })
+            .collect();
+
+        write_file(filename, &header, &rows, dialect)
+    }
+}
+
+impl fmt::Display for EnergyBalanceEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flagged = self.flagged();
+        writeln!(
+            f,
+            "Estimated energy balance: {} of {} space-timesteps with a residual above {} W",
+            flagged.len(),
+            self.rows.len(),
+            self.config.threshold
+        )?;
+
+        // Terms that could not be estimated make the residuals meaningless
+        let mut missing: Vec<&str> = Vec::new();
+        for name in self.rows.iter().flat_map(|r| r.missing()) {
+            if !missing.contains(&name) {
+                missing.push(name);
+            }
+        }
+        if !missing.is_empty() {
+            writeln!(f, "Not estimated (left out of the residuals): {}", missing.join(", "))?;
+        }
+
+        let mut names: Vec<&String> = Vec::new();
+        for r in self.rows.iter() {
+            if !names.contains(&&r.space_name) {
+                names.push(&r.space_name);
+            }
+        }
+        writeln!(f, "{:<16} {:>20} {:>20}", "Space", "Max |residual| [W]", "Flagged timesteps")?;
+        for name in names {
+            let rows = self.rows.iter().filter(|r| &r.space_name == name);
+            let max = rows.clone().map(|r| r.residual.abs()).fold(0.0, f64::max);
+            let count = rows.filter(|r| r.residual.abs() > self.config.threshold).count();
+            writeln!(f, "{:<16} {:>20.2} {:>20}", name, max, count)?;
+        }
+        Ok(())
+    }
+}
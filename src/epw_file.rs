@@ -0,0 +1,275 @@
+/// Number of header lines in an EPW file
+pub const HEADER_LINES: usize = 8;
+
+/// Columns (starting from 0) of the data records of an EPW file
+pub const YEAR: usize = 0;
+pub const MONTH: usize = 1;
+pub const DAY: usize = 2;
+pub const HOUR: usize = 3;
+pub const DRY_BULB_TEMPERATURE: usize = 6;
+pub const DEW_POINT_TEMPERATURE: usize = 7;
+pub const RELATIVE_HUMIDITY: usize = 8;
+pub const ATMOSPHERIC_PRESSURE: usize = 9;
+pub const GLOBAL_HORIZONTAL_RADIATION: usize = 13;
+pub const DIRECT_NORMAL_RADIATION: usize = 14;
+pub const DIFFUSE_HORIZONTAL_RADIATION: usize = 15;
+pub const WIND_DIRECTION: usize = 20;
+pub const WIND_SPEED: usize = 21;
+
+/// Number of fields in each data record
+pub const FIELDS: usize = 35;
+
+/// The value that marks a field as missing, for the fields that have one
+pub fn missing_value(field: usize) -> Option<f64> {
+    match field {
+        DRY_BULB_TEMPERATURE | DEW_POINT_TEMPERATURE => Some(99.9),
+        RELATIVE_HUMIDITY | WIND_SPEED => Some(999.),
+        ATMOSPHERIC_PRESSURE => Some(999999.),
+        GLOBAL_HORIZONTAL_RADIATION | DIRECT_NORMAL_RADIATION | DIFFUSE_HORIZONTAL_RADIATION => Some(9999.),
+        _ => None,
+    }
+}
+
+/// Whether a value of a field is its missing value marker
+pub fn is_missing(field: usize, value: f64) -> bool {
+    missing_value(field).map_or(false, |m| (value - m).abs() < 1e-9)
+}
+
+/// The location, as written in the first line of an EPW file
+#[derive(Clone, Debug)]
+pub struct EpwLocation {
+    pub city: String,
+    pub country: String,
+
+    /// In degrees, positive to the North
+    pub latitude: f64,
+
+    /// In degrees, positive to the East
+    pub longitude: f64,
+
+    /// Hours from GMT
+    pub timezone: f64,
+
+    /// In m
+    pub elevation: f64,
+}
+
+/// The raw contents of an EPW file, kept as text so that it
+/// can be modified and written back without losing information
+#[derive(Clone, Debug)]
+pub struct EpwFile {
+    /// The header lines, unchanged
+    pub header: Vec<String>,
+
+    /// The fields of each data record
+    pub records: Vec<Vec<String>>,
+}
+
+impl EpwFile {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let content = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        match Self::parse(&content) {
+            Some(v) => Ok(v),
+            None => Err(format!("EPW file '{}' has an incomplete header", filename)),
+        }
+    }
+
+    /// Splits the contents of an EPW file into its header and records,
+    /// returning `None` if the header is incomplete
+    pub fn parse(content: &str) -> Option<Self> {
+        let mut lines = content.lines();
+        let header: Vec<String> = lines.by_ref().take(HEADER_LINES).map(|l| l.to_string()).collect();
+        if header.len() < HEADER_LINES {
+            return None;
+        }
+        let records = lines
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| l.split(',').map(|f| f.trim().to_string()).collect())
+            .collect();
+        Some(EpwFile { header, records })
+    }
+
+    /// Reads the LOCATION line of the header
+    pub fn location(&self) -> Result<EpwLocation, String> {
+        let fields: Vec<&str> = self.header[0].split(',').map(|f| f.trim()).collect();
+        if fields.len() < 10 || fields[0] != "LOCATION" {
+            return Err("The first line of the EPW file is not a valid LOCATION".to_string());
+        }
+        let number = |i: usize, name: &str| -> Result<f64, String> {
+            match fields[i].parse::<f64>() {
+                Ok(v) => Ok(v),
+                Err(_) => Err(format!("Invalid {} '{}' in the EPW LOCATION", name, fields[i])),
+            }
+        };
+        Ok(EpwLocation {
+            city: fields[1].to_string(),
+            country: fields[3].to_string(),
+            latitude: number(6, "latitude")?,
+            longitude: number(7, "longitude")?,
+            timezone: number(8, "timezone")?,
+            elevation: number(9, "elevation")?,
+        })
+    }
+
+    /// Reads a numeric field of a record
+    pub fn value(&self, record: usize, field: usize) -> Result<f64, String> {
+        match self.records[record].get(field).map(|f| f.parse::<f64>()) {
+            Some(Ok(v)) => Ok(v),
+            _ => Err(format!("Could not read field {} in record {} of the EPW file", field, record + 1)),
+        }
+    }
+
+    /// Overwrites a numeric field of a record, with a certain number of decimals
+    pub fn set_value(&mut self, record: usize, field: usize, value: f64, decimals: usize) {
+        let record = &mut self.records[record];
+        if record.len() <= field {
+            record.resize(field + 1, String::new());
+        }
+        record[field] = format!("{:.*}", decimals, value);
+    }
+
+    /// The month of a record
+    pub fn month(&self, record: usize) -> Result<usize, String> {
+        let month = self.value(record, MONTH)?;
+        if month < 1. || month > 12. {
+            return Err(format!("Invalid month {} in record {} of the EPW file", month, record + 1));
+        }
+        Ok(month as usize)
+    }
+
+    /// The mean of a field over the records of each month, leaving out
+    /// missing values. Months without values have no mean.
+    pub fn monthly_means(&self, field: usize) -> Result<[Option<f64>; 12], String> {
+        let mut sums = [0.0; 12];
+        let mut counts = [0usize; 12];
+        for i in 0..self.records.len() {
+            let month = self.month(i)?;
+            let value = self.value(i, field)?;
+            if !is_missing(field, value) {
+                sums[month - 1] += value;
+                counts[month - 1] += 1;
+            }
+        }
+        let mut means = [None; 12];
+        for (i, mean) in means.iter_mut().enumerate() {
+            if counts[i] > 0 {
+                *mean = Some(sums[i] / counts[i] as f64);
+            }
+        }
+        Ok(means)
+    }
+
+    pub fn write(&self, filename: &str) -> Result<(), String> {
+        let mut content = String::new();
+        for line in self.header.iter() {
+            content.push_str(line);
+            content.push_str("\r\n");
+        }
+        for record in self.records.iter() {
+            content.push_str(&record.join(","));
+            content.push_str("\r\n");
+        }
+        match std::fs::write(filename, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(records: &[&str]) -> EpwFile {
+        let mut content = String::from(
+            "LOCATION,Santiago,-,CHL,SRC-TMYx,855740,-33.38,-70.78,-4.0,482.0\r\n\
+             DESIGN CONDITIONS,0\r\n\
+             TYPICAL/EXTREME PERIODS,0\r\n\
+             GROUND TEMPERATURES,0\r\n\
+             HOLIDAYS/DAYLIGHT SAVINGS,No,0,0,0\r\n\
+             COMMENTS 1,\r\n\
+             COMMENTS 2,\r\n\
+             DATA PERIODS,1,1,Data,Sunday, 1/ 1,12/31\r\n",
+        );
+        for r in records {
+            content.push_str(r);
+            content.push_str("\r\n");
+        }
+        EpwFile::parse(&content).unwrap()
+    }
+
+    fn record(month: usize, day: usize, hour: usize, temperature: f64, ghi: f64) -> String {
+        let mut fields: Vec<String> = vec!["0".to_string(); FIELDS];
+        fields[YEAR] = "2021".to_string();
+        fields[MONTH] = month.to_string();
+        fields[DAY] = day.to_string();
+        fields[HOUR] = hour.to_string();
+        fields[DRY_BULB_TEMPERATURE] = temperature.to_string();
+        fields[GLOBAL_HORIZONTAL_RADIATION] = ghi.to_string();
+        fields.join(",")
+    }
+
+    #[test]
+    fn incomplete_header() {
+        assert!(EpwFile::parse("LOCATION,Santiago\nDESIGN CONDITIONS,0\n").is_none());
+    }
+
+    #[test]
+    fn location() {
+        let location = file(&[]).location().unwrap();
+        assert_eq!(location.city, "Santiago");
+        assert_eq!(location.country, "CHL");
+        assert!((location.latitude + 33.38).abs() < 1e-9);
+        assert!((location.longitude + 70.78).abs() < 1e-9);
+        assert!((location.timezone + 4.).abs() < 1e-9);
+        assert!((location.elevation - 482.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn records() {
+        let records = [record(1, 1, 1, 20.5, 0.), record(1, 1, 2, 21.5, 9999.)];
+        let records: Vec<&str> = records.iter().map(|r| r.as_str()).collect();
+        let mut epw = file(&records);
+        assert_eq!(epw.records.len(), 2);
+        assert_eq!(epw.month(1).unwrap(), 1);
+        assert!((epw.value(1, DRY_BULB_TEMPERATURE).unwrap() - 21.5).abs() < 1e-9);
+        assert!(is_missing(GLOBAL_HORIZONTAL_RADIATION, epw.value(1, GLOBAL_HORIZONTAL_RADIATION).unwrap()));
+        assert!(!is_missing(GLOBAL_HORIZONTAL_RADIATION, epw.value(0, GLOBAL_HORIZONTAL_RADIATION).unwrap()));
+
+        epw.set_value(0, DRY_BULB_TEMPERATURE, 18.04, 1);
+        assert_eq!(epw.records[0][DRY_BULB_TEMPERATURE], "18.0");
+        assert!(epw.value(0, 100).is_err());
+    }
+
+    #[test]
+    fn invalid_month() {
+        let records = [record(13, 1, 1, 20., 0.)];
+        let epw = file(&[records[0].as_str()]);
+        assert!(epw.month(0).is_err());
+        assert!(epw.monthly_means(DRY_BULB_TEMPERATURE).is_err());
+    }
+
+    #[test]
+    fn monthly_means() {
+        let records = [
+            record(1, 1, 1, 10., 100.),
+            record(1, 1, 2, 20., 9999.),
+            record(3, 1, 1, 99.9, 300.),
+            record(3, 1, 2, 5., 9999.),
+        ];
+        let records: Vec<&str> = records.iter().map(|r| r.as_str()).collect();
+        let epw = file(&records);
+
+        let temperature = epw.monthly_means(DRY_BULB_TEMPERATURE).unwrap();
+        assert_eq!(temperature[0], Some(15.));
+        assert_eq!(temperature[1], None);
+        assert_eq!(temperature[2], Some(5.));
+
+        let ghi = epw.monthly_means(GLOBAL_HORIZONTAL_RADIATION).unwrap();
+        assert_eq!(ghi[0], Some(100.));
+        assert_eq!(ghi[2], Some(300.));
+    }
+}
@@ -0,0 +1,207 @@
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use weather::current_weather::CurrentWeather;
+use weather::Weather;
+
+use crate::date_utils::{hour_of_year, hours_between};
+use crate::rng::Rng;
+
+/// The errors of the weather forecasts given to the occupants,
+/// which grow linearly with the forecast horizon. Read from JSON as
+///
+/// ```json
+/// {"temperature_bias": 0.2, "temperature_noise": 0.4, "radiation_noise": 0.05}
+/// ```
+///
+/// The errors are drawn from the seed of the run (see `RunOptions::seed`),
+/// so the configuration takes no seed of its own.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForecastConfig {
+    /// Systematic error of the dry bulb temperature, in K per hour of horizon
+    #[serde(default)]
+    pub temperature_bias: f64,
+
+    /// Standard deviation of the error of the dry bulb temperature, in K per hour of horizon
+    #[serde(default)]
+    pub temperature_noise: f64,
+
+    /// Systematic relative error of the solar radiation, per hour of horizon
+    #[serde(default)]
+    pub radiation_bias: f64,
+
+    /// Standard deviation of the relative error of the solar radiation, per hour of horizon
+    #[serde(default)]
+    pub radiation_noise: f64,
+}
+
+impl ForecastConfig {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        let config: Self = match serde_json::from_str(&data) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not parse forecast configuration '{}': {}", filename, e)),
+        };
+        if config.temperature_noise < 0.0 || config.radiation_noise < 0.0 {
+            return Err("Forecast noise cannot be negative".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// The forecast issued at a certain date. The present and the past
+/// are known exactly; the future carries the errors of the `ForecastConfig`.
+///
+/// The error of each forecast depends only on the seed and on the
+/// issue and target dates, so asking twice gives the same answer.
+pub struct ForecastWeather<'a> {
+    weather: &'a dyn Weather,
+    config: &'a ForecastConfig,
+    issued: Date,
+    seed: u64,
+}
+
+impl<'a> ForecastWeather<'a> {
+    pub fn new(weather: &'a dyn Weather, config: &'a ForecastConfig, issued: Date, seed: u64) -> Self {
+        ForecastWeather {
+            weather,
+            config,
+            issued,
+            seed,
+        }
+    }
+}
+
+impl<'a> Weather for ForecastWeather<'a> {
+    fn get_weather_data(&self, date: Date) -> CurrentWeather {
+        let truth = self.weather.get_weather_data(date);
+        let horizon = hours_between(&self.issued, &date);
+        if horizon <= 0.0 {
+            return truth;
+        }
+
+        // One stream per issue and target minute
+        let issued = (hour_of_year(&self.issued) * 60.).round() as u64;
+        let target = (hour_of_year(&date) * 60.).round() as u64;
+        let mut rng = Rng::derive(self.seed, (issued << 20) ^ target);
+
+        let c = self.config;
+        let temperature = truth.dry_bulb_temperature
+            + rng.normal(c.temperature_bias * horizon, c.temperature_noise * horizon);
+        let solar = (1. + rng.normal(c.radiation_bias * horizon, c.radiation_noise * horizon)).max(0.0);
+
+        // Keep the forecast moisture content
+        let dew_point_temperature = truth.dew_point_temperature.min(temperature);
+        let relative_humidity = relative_humidity(temperature, dew_point_temperature);
+
+        CurrentWeather {
+            dry_bulb_temperature: temperature,
+            dew_point_temperature,
+            relative_humidity,
+            direct_normal_radiation: solar * truth.direct_normal_radiation,
+            diffuse_horizontal_radiation: solar * truth.diffuse_horizontal_radiation,
+            ..truth
+        }
+    }
+}
+
+/// Relative humidity (in %) from the dry bulb and dew point
+/// temperatures (in C), inverting the Magnus formula
+fn relative_humidity(dry_bulb_temperature: f64, dew_point_temperature: f64) -> f64 {
+    const B: f64 = 17.62;
+    const C: f64 = 243.12;
+    let gamma_dew = B * dew_point_temperature / (C + dew_point_temperature);
+    let gamma_dry = B * dry_bulb_temperature / (C + dry_bulb_temperature);
+    (100. * (gamma_dew - gamma_dry).exp()).min(100.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date_utils::date_from_hour_of_year;
+    use crate::statistics::standard_deviation;
+    use crate::weather_sources::ConstantWeather;
+
+    fn truth() -> ConstantWeather {
+        serde_json::from_str(r#"{"dry_bulb_temperature": 20.0, "relative_humidity": 60.0, "direct_normal_radiation": 500.0, "diffuse_horizontal_radiation": 100.0}"#)
+            .unwrap()
+    }
+
+    fn noisy() -> ForecastConfig {
+        ForecastConfig {
+            temperature_bias: 0.2,
+            temperature_noise: 0.5,
+            radiation_bias: 0.01,
+            radiation_noise: 0.05,
+        }
+    }
+
+    fn date(hour_of_year: f64) -> Date {
+        date_from_hour_of_year(hour_of_year)
+    }
+
+    #[test]
+    fn perfect_forecast() {
+        let weather = truth();
+        let config = ForecastConfig::default();
+        let forecast = ForecastWeather::new(&weather, &config, date(4000.), 1);
+        for horizon in [0.5, 1., 24., 72.].iter() {
+            let record = forecast.get_weather_data(date(4000. + horizon));
+            assert_eq!(record.dry_bulb_temperature, 20.);
+            assert_eq!(record.direct_normal_radiation, 500.);
+            assert_eq!(record.diffuse_horizontal_radiation, 100.);
+            assert!((record.relative_humidity - 60.).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn present_and_past_are_known() {
+        let weather = truth();
+        let config = noisy();
+        let forecast = ForecastWeather::new(&weather, &config, date(4000.), 1);
+        for t in [4000., 3999., 3000.].iter() {
+            let record = forecast.get_weather_data(date(*t));
+            assert_eq!(record.dry_bulb_temperature, 20.);
+            assert_eq!(record.direct_normal_radiation, 500.);
+        }
+    }
+
+    #[test]
+    fn same_seed_and_dates_same_forecast() {
+        let weather = truth();
+        let config = noisy();
+        let a = ForecastWeather::new(&weather, &config, date(4000.), 7);
+        let b = ForecastWeather::new(&weather, &config, date(4000.), 7);
+        let other_seed = ForecastWeather::new(&weather, &config, date(4000.), 8);
+        let target = date(4012.);
+        let value = a.get_weather_data(target).dry_bulb_temperature;
+        assert_eq!(value, a.get_weather_data(target).dry_bulb_temperature);
+        assert_eq!(value, b.get_weather_data(target).dry_bulb_temperature);
+        assert_ne!(value, other_seed.get_weather_data(target).dry_bulb_temperature);
+    }
+
+    #[test]
+    fn spread_grows_with_the_horizon() {
+        let weather = truth();
+        let config = ForecastConfig {
+            temperature_noise: 0.5,
+            ..ForecastConfig::default()
+        };
+        let errors = |horizon: f64| -> Vec<f64> {
+            (0..500)
+                .map(|i| {
+                    let issued = 10. * i as f64;
+                    let forecast = ForecastWeather::new(&weather, &config, date(issued), 3);
+                    forecast.get_weather_data(date(issued + horizon)).dry_bulb_temperature - 20.
+                })
+                .collect()
+        };
+        let short = standard_deviation(&errors(1.));
+        let long = standard_deviation(&errors(24.));
+        assert!(short > 0.3 && short < 0.7, "{}", short);
+        assert!(long > 8. && long < 16., "{}", long);
+    }
+}
@@ -0,0 +1,53 @@
+/// A 64-bit FNV-1a hash of some content. It is not cryptographic,
+/// but it is stable across platforms and versions of Rust, which
+/// makes it suitable for identifying inputs.
+#[derive(Clone, Debug)]
+pub struct ContentHash {
+    state: u64,
+}
+
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+impl Default for ContentHash {
+    fn default() -> Self {
+        ContentHash { state: OFFSET_BASIS }
+    }
+}
+
+impl ContentHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.state ^= *b as u64;
+            self.state = self.state.wrapping_mul(PRIME);
+        }
+    }
+
+    /// Adds a string, followed by a separator so that
+    /// `("ab", "c")` and `("a", "bc")` hash differently
+    pub fn update_str(&mut self, s: &str) {
+        self.update(s.as_bytes());
+        self.update(&[0]);
+    }
+
+    /// Adds the contents of a file
+    pub fn update_file(&mut self, filename: &str) -> Result<(), String> {
+        match std::fs::read(filename) {
+            Ok(bytes) => {
+                self.update(&bytes);
+                self.update(&[0]);
+                Ok(())
+            }
+            Err(e) => Err(format!("Could not read file '{}': {}", filename, e)),
+        }
+    }
+
+    /// The hash, as 16 hexadecimal digits
+    pub fn hex(&self) -> String {
+        format!("{:016x}", self.state)
+    }
+}
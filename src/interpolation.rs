@@ -0,0 +1,286 @@
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use weather::current_weather::CurrentWeather;
+use weather::Weather;
+
+use crate::date_utils::{date_from_hour_of_year, day_of_year, hour_of_year};
+
+/// Below this cosine of the solar zenith angle, the sun is
+/// considered to be too low for scaling the radiation
+const MIN_COS_ZENITH: f64 = 0.02;
+
+/// How values between hourly records are obtained
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Interpolation {
+    /// The value of the last record is held until the next one
+    Step,
+
+    /// Every variable is interpolated linearly
+    Linear,
+
+    /// Temperatures and humidity are interpolated linearly, while the
+    /// radiation follows the position of the sun. The location defaults
+    /// to that of the EPW file, when there is one.
+    Solar {
+        #[serde(default)]
+        latitude: Option<f64>,
+
+        #[serde(default)]
+        longitude: Option<f64>,
+
+        #[serde(default)]
+        timezone: Option<f64>,
+    },
+}
+
+/// The location used for calculating the position of the sun
+#[derive(Clone, Copy, Debug)]
+pub struct SolarLocation {
+    /// In degrees, positive to the North
+    pub latitude: f64,
+
+    /// In degrees, positive to the East
+    pub longitude: f64,
+
+    /// Hours from GMT
+    pub timezone: f64,
+}
+
+impl SolarLocation {
+    /// Cosine of the solar zenith angle at a certain (local standard time) date
+    pub fn cos_zenith(&self, date: &Date) -> f64 {
+        let doy = day_of_year(date) as f64;
+        let declination = (23.45 * (360. / 365. * (284. + doy)).to_radians().sin()).to_radians();
+
+        // Equation of time, in minutes
+        let b = (360. * (doy - 81.) / 364.).to_radians();
+        let equation_of_time = 9.87 * (2. * b).sin() - 7.53 * b.cos() - 1.5 * b.sin();
+
+        let solar_time = date.hour + (4. * (self.longitude - 15. * self.timezone) + equation_of_time) / 60.;
+        let hour_angle = (15. * (solar_time - 12.)).to_radians();
+        let latitude = self.latitude.to_radians();
+
+        latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos()
+    }
+}
+
+/// A weather that interpolates between the hourly records of another one
+pub struct InterpolatedWeather {
+    weather: Box<dyn Weather + Send + Sync>,
+    method: Interpolation,
+
+    /// Only used by the solar interpolation
+    location: Option<SolarLocation>,
+}
+
+impl InterpolatedWeather {
+    /// Wraps a weather. The `location` is required by the solar interpolation.
+    pub fn new(
+        weather: Box<dyn Weather + Send + Sync>,
+        method: Interpolation,
+        location: Option<SolarLocation>,
+    ) -> Result<Self, String> {
+        if let Interpolation::Solar { .. } = method {
+            if location.is_none() {
+                return Err("Solar interpolation needs a latitude, longitude and timezone".to_string());
+            }
+        }
+        Ok(InterpolatedWeather {
+            weather,
+            method,
+            location,
+        })
+    }
+
+    /// Interpolates a radiation following the position of the sun: the
+    /// ratio between the radiation and the cosine of the zenith angle is
+    /// interpolated, and then scaled back with the current position.
+    fn solar(&self, location: &SolarLocation, dates: [&Date; 3], values: [f64; 2], w: f64, horizontal: bool) -> f64 {
+        let [a, b, now] = dates;
+        let cos_now = location.cos_zenith(now);
+        if cos_now <= 0.0 {
+            return 0.0;
+        }
+        let cos_a = location.cos_zenith(a);
+        let cos_b = location.cos_zenith(b);
+        let linear = values[0] + (values[1] - values[0]) * w;
+
+        // Horizontal values scale with the cosine of the zenith, normal ones do not
+        let ratio = |value: f64, cos: f64| if horizontal { value / cos } else { value };
+        let scale = if horizontal { cos_now } else { 1.0 };
+        let value = match (cos_a > MIN_COS_ZENITH, cos_b > MIN_COS_ZENITH) {
+            (true, true) => {
+                let ra = ratio(values[0], cos_a);
+                let rb = ratio(values[1], cos_b);
+                scale * (ra + (rb - ra) * w)
+            }
+            (true, false) => scale * ratio(values[0], cos_a),
+            (false, true) => scale * ratio(values[1], cos_b),
+            (false, false) => linear,
+        };
+        value.max(0.0)
+    }
+}
+
+impl Weather for InterpolatedWeather {
+    fn get_weather_data(&self, date: Date) -> CurrentWeather {
+        let t = hour_of_year(&date);
+        let base = t.floor();
+        let w = t - base;
+
+        let before_date = date_from_hour_of_year(base);
+        let before = self.weather.get_weather_data(before_date);
+        let step = match self.method {
+            Interpolation::Step => true,
+            _ => false,
+        };
+        if step || w == 0.0 {
+            return CurrentWeather { date, ..before };
+        }
+
+        let after_date = date_from_hour_of_year(base + 1.);
+        let after = self.weather.get_weather_data(after_date);
+        let lerp = |a: f64, b: f64| a + (b - a) * w;
+
+        let (direct_normal_radiation, diffuse_horizontal_radiation) = match (&self.method, &self.location) {
+            (Interpolation::Solar { .. }, Some(location)) => {
+                let dates = [&before_date, &after_date, &date];
+                (
+                    self.solar(
+                        location,
+                        dates,
+                        [before.direct_normal_radiation, after.direct_normal_radiation],
+                        w,
+                        false,
+                    ),
+                    self.solar(
+                        location,
+                        dates,
+                        [before.diffuse_horizontal_radiation, after.diffuse_horizontal_radiation],
+                        w,
+                        true,
+                    ),
+                )
+            }
+            _ => (
+                lerp(before.direct_normal_radiation, after.direct_normal_radiation),
+                lerp(before.diffuse_horizontal_radiation, after.diffuse_horizontal_radiation),
+            ),
+        };
+
+        CurrentWeather {
+            date,
+            dry_bulb_temperature: lerp(before.dry_bulb_temperature, after.dry_bulb_temperature),
+            dew_point_temperature: lerp(before.dew_point_temperature, after.dew_point_temperature),
+            relative_humidity: lerp(before.relative_humidity, after.relative_humidity),
+            wind_speed: lerp(before.wind_speed, after.wind_speed),
+            direct_normal_radiation,
+            diffuse_horizontal_radiation,
+            ..before
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather_sources::{weather_record, ConstantWeather};
+
+    /// A weather whose temperature is given by a function of the date
+    struct FunctionWeather(fn(&Date) -> f64);
+
+    impl Weather for FunctionWeather {
+        fn get_weather_data(&self, date: Date) -> CurrentWeather {
+            let v = (self.0)(&date);
+            weather_record(date, v, 50., 10. * v, 5. * v, 0.)
+        }
+    }
+
+    fn equator() -> SolarLocation {
+        SolarLocation {
+            latitude: 0.,
+            longitude: 0.,
+            timezone: 0.,
+        }
+    }
+
+    fn equinox(hour: f64) -> Date {
+        Date { month: 3, day: 21, hour }
+    }
+
+    #[test]
+    fn sun_at_the_equinox() {
+        // Overhead at noon on the equator (within the equation of time)...
+        assert!(equator().cos_zenith(&equinox(12.)) > 0.999);
+        // ... at 45 degrees at noon, 45 degrees to the North...
+        let north = SolarLocation {
+            latitude: 45.,
+            ..equator()
+        };
+        assert!((north.cos_zenith(&equinox(12.)) - 45f64.to_radians().cos()).abs() < 0.01);
+        // ... and on the other side of the Earth at midnight
+        assert!(equator().cos_zenith(&equinox(0.)) < -0.99);
+    }
+
+    #[test]
+    fn no_radiation_at_night() {
+        let weather: ConstantWeather = serde_json::from_str(
+            r#"{"dry_bulb_temperature": 20.0, "direct_normal_radiation": 500.0, "diffuse_horizontal_radiation": 100.0}"#,
+        )
+        .unwrap();
+        let method = Interpolation::Solar {
+            latitude: None,
+            longitude: None,
+            timezone: None,
+        };
+        let weather = InterpolatedWeather::new(Box::new(weather), method, Some(equator())).unwrap();
+        let record = weather.get_weather_data(equinox(0.5));
+        assert_eq!(record.direct_normal_radiation, 0.0);
+        assert_eq!(record.diffuse_horizontal_radiation, 0.0);
+
+        // But there is during the day
+        let record = weather.get_weather_data(equinox(12.5));
+        assert!(record.direct_normal_radiation > 0.0);
+        assert!(record.diffuse_horizontal_radiation > 0.0);
+    }
+
+    #[test]
+    fn records_at_the_exact_hour() {
+        let methods = vec![
+            Interpolation::Step,
+            Interpolation::Linear,
+            Interpolation::Solar {
+                latitude: None,
+                longitude: None,
+                timezone: None,
+            },
+        ];
+        for method in methods {
+            let weather = InterpolatedWeather::new(Box::new(FunctionWeather(|d| d.hour)), method, Some(equator())).unwrap();
+            let record = weather.get_weather_data(equinox(10.));
+            assert_eq!(record.dry_bulb_temperature, 10.);
+            assert_eq!(record.direct_normal_radiation, 100.);
+            assert_eq!(record.diffuse_horizontal_radiation, 50.);
+        }
+
+        let weather = InterpolatedWeather::new(Box::new(FunctionWeather(|d| d.hour)), Interpolation::Linear, None).unwrap();
+        assert!((weather.get_weather_data(equinox(10.25)).dry_bulb_temperature - 10.25).abs() < 1e-9);
+        let weather = InterpolatedWeather::new(Box::new(FunctionWeather(|d| d.hour)), Interpolation::Step, None).unwrap();
+        assert_eq!(weather.get_weather_data(equinox(10.75)).dry_bulb_temperature, 10.);
+    }
+
+    #[test]
+    fn wraps_from_december_to_january() {
+        let new_year = |d: &Date| if d.month == 1 && d.day == 1 && d.hour < 1. { 10. } else { 20. };
+        let weather = InterpolatedWeather::new(Box::new(FunctionWeather(new_year)), Interpolation::Linear, None).unwrap();
+        let date = Date {
+            month: 12,
+            day: 31,
+            hour: 23.5,
+        };
+        let record = weather.get_weather_data(date);
+        assert!((record.dry_bulb_temperature - 15.).abs() < 1e-9);
+        assert!(record.date == date);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use building_model::building::Building;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_results::SimulationResults;
+
+use crate::metering::{EndUse, Meters};
+use crate::results_json::*;
+use crate::state_elements::{space_values, SPACE_DRY_BULB_TEMPERATURE};
+use crate::statistics::{linear_trend, Statistics};
+
+/// The range of space temperatures considered comfortable
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ComfortBand {
+    /// Lower limit, in C
+    pub lower: f64,
+
+    /// Upper limit, in C
+    pub upper: f64,
+}
+
+impl Default for ComfortBand {
+    fn default() -> Self {
+        ComfortBand {
+            lower: 20.0,
+            upper: 26.0,
+        }
+    }
+}
+
+/// Key Performance Indicators of a single space
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SpaceKpi {
+    pub name: String,
+
+    /// Energy consumed by heating/cooling, in kWh
+    pub heating_energy: f64,
+
+    /// Energy consumed by lighting, in kWh
+    pub lighting_energy: f64,
+
+    /// Hours in which the temperature was outside the comfort band
+    pub discomfort_hours: f64,
+
+    /// Degree-hours below the lower limit of the comfort band
+    pub degree_hours_below: f64,
+
+    /// Degree-hours above the upper limit of the comfort band
+    pub degree_hours_above: f64,
+}
+
+/// The number of times an action was taken in a space
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ActionCount {
+    pub action: String,
+    pub space: String,
+    pub count: usize,
+}
+
+/// A summary of the Key Performance Indicators of a simulation run
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct KpiSummary {
+    /// The comfort band used for calculating discomfort
+    pub comfort_band: ComfortBand,
+
+    /// Simulated hours
+    pub simulated_hours: f64,
+
+    /// Hours in which the person attended the building
+    pub attended_hours: f64,
+
+    /// KPIs per space
+    pub spaces: Vec<SpaceKpi>,
+
+    /// Statistics of the `current_comfort` of the person
+    pub current_comfort: Statistics,
+
+    /// Statistics of the `potential_comfort` of the person
+    pub potential_comfort: Statistics,
+
+    /// Statistics of the dwelling satisfaction of the person
+    #[serde(default)]
+    pub satisfaction: Statistics,
+
+    /// Change in dwelling satisfaction per day (least-squares slope)
+    pub satisfaction_trend: f64,
+
+    /// Number of actions taken, by type and space
+    pub actions: Vec<ActionCount>,
+}
+
+impl KpiSummary {
+    /// Summarizes some `SimulationResults`, obtained with `n`
+    /// timesteps per hour.
+    pub fn new(results: &SimulationResults, building: &Building, n: usize, band: ComfortBand) -> Result<Self, String> {
+        let data = match serde_json::to_value(results) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize results: {}", e)),
+        };
+        Self::from_json(&data, building, n, band)
+    }
+
+    /// Summarizes the JSON representation of some `SimulationResults`,
+    /// obtained with `n` timesteps per hour.
+    pub fn from_json(data: &Value, building: &Building, n: usize, band: ComfortBand) -> Result<Self, String> {
+        let meters = Meters::from_json(data, building, n)?;
+        let data = timesteps(data)?;
+        let dt = 1. / n as f64; // hours
+
+        let mut spaces: BTreeMap<usize, SpaceKpi> = BTreeMap::new();
+        let mut current_comfort = Vec::new();
+        let mut potential_comfort = Vec::new();
+        let mut satisfaction_time = Vec::new();
+        let mut satisfaction = Vec::new();
+        let mut actions: BTreeMap<(String, String), usize> = BTreeMap::new();
+        let mut attended_hours = 0.0;
+
+        for (i, tstep) in data.iter().enumerate() {
+            let elements = tstep.get("state_elements").unwrap_or(&Value::Null);
+
+            for (space, temp) in space_values(elements, SPACE_DRY_BULB_TEMPERATURE) {
+                let kpi = space_kpi(&mut spaces, building, space)?;
+                if temp < band.lower {
+                    kpi.discomfort_hours += dt;
+                    kpi.degree_hours_below += (band.lower - temp) * dt;
+                } else if temp > band.upper {
+                    kpi.discomfort_hours += dt;
+                    kpi.degree_hours_above += (temp - band.upper) * dt;
+                }
+            }
+
+            let person = controller(tstep, "person")?;
+            if !get_bool(person, "attended")? {
+                continue;
+            }
+            attended_hours += dt;
+
+            current_comfort.push(get_f64(person, "current_comfort")?);
+            potential_comfort.push(get_f64(person, "potential_comfort")?);
+            satisfaction_time.push(i as f64 * dt / 24.);
+            satisfaction.push(get_f64(person, "dwelling_satisfaction_before")?);
+
+            for action in actions_taken(person)? {
+                *actions.entry(action).or_insert(0) += 1;
+            }
+        }
+
+        for meter in meters.meters.iter() {
+            let kpi = space_kpi(&mut spaces, building, meter.space)?;
+            match meter.end_use {
+                EndUse::HeatingCooling => kpi.heating_energy += meter.total(),
+                EndUse::Lighting => kpi.lighting_energy += meter.total(),
+            }
+        }
+
+        Ok(KpiSummary {
+            comfort_band: band,
+            simulated_hours: data.len() as f64 * dt,
+            attended_hours,
+            spaces: spaces.into_iter().map(|(_, v)| v).collect(),
+            current_comfort: Statistics::new(&current_comfort),
+            potential_comfort: Statistics::new(&potential_comfort),
+            satisfaction: Statistics::new(&satisfaction),
+            satisfaction_trend: linear_trend(&satisfaction_time, &satisfaction),
+            actions: actions
+                .into_iter()
+                .map(|((action, space), count)| ActionCount { action, space, count })
+                .collect(),
+        })
+    }
+
+    /// Total energy consumed by heating/cooling, in kWh
+    pub fn heating_energy(&self) -> f64 {
+        self.spaces.iter().map(|s| s.heating_energy).sum()
+    }
+
+    /// Total energy consumed by lighting, in kWh
+    pub fn lighting_energy(&self) -> f64 {
+        self.spaces.iter().map(|s| s.lighting_energy).sum()
+    }
+
+    /// Total number of actions taken
+    pub fn total_actions(&self) -> usize {
+        self.actions.iter().map(|a| a.count).sum()
+    }
+
+    /// The names of the scalar indicators returned by `get`
+    pub fn names() -> &'static [&'static str] {
+        &[
+            "mean_current_comfort",
+            "mean_potential_comfort",
+            "mean_satisfaction",
+            "satisfaction_trend",
+            "heating_energy",
+            "lighting_energy",
+            "total_energy",
+            "discomfort_hours",
+            "degree_hours",
+            "total_actions",
+            "action_frequency",
+        ]
+    }
+
+    /// Gets a scalar indicator by name
+    pub fn get(&self, name: &str) -> Result<f64, String> {
+        let v = match name {
+            "mean_current_comfort" => self.current_comfort.mean,
+            "mean_potential_comfort" => self.potential_comfort.mean,
+            "mean_satisfaction" => self.satisfaction.mean,
+            "satisfaction_trend" => self.satisfaction_trend,
+            "heating_energy" => self.heating_energy(),
+            "lighting_energy" => self.lighting_energy(),
+            "total_energy" => self.heating_energy() + self.lighting_energy(),
+            "discomfort_hours" => self.spaces.iter().map(|s| s.discomfort_hours).sum(),
+            "degree_hours" => self
+                .spaces
+                .iter()
+                .map(|s| s.degree_hours_below + s.degree_hours_above)
+                .sum(),
+            "total_actions" => self.total_actions() as f64,
+            // actions per attended hour
+            "action_frequency" => {
+                if self.attended_hours > 0. {
+                    self.total_actions() as f64 / self.attended_hours
+                } else {
+                    0.
+                }
+            }
+            _ => return Err(format!("Unknown KPI '{}'", name)),
+        };
+        Ok(v)
+    }
+
+    /// Writes the summary as JSON
+    pub fn write_json(&self, filename: &str) -> Result<(), String> {
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize KPI summary: {}", e)),
+        };
+        match std::fs::write(filename, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+        }
+    }
+
+    /// Writes the summary as a human-readable table
+    pub fn write_table(&self, filename: &str) -> Result<(), String> {
+        match std::fs::write(filename, format!("{}", self)) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+        }
+    }
+}
+
+/// Gets the KPIs of a space, registering it if it was not there
+fn space_kpi<'a>(spaces: &'a mut BTreeMap<usize, SpaceKpi>, building: &Building, index: usize) -> Result<&'a mut SpaceKpi, String> {
+    if !spaces.contains_key(&index) {
+        let name = building.get_space(index)?.name().clone();
+        spaces.insert(
+            index,
+            SpaceKpi {
+                name,
+                ..SpaceKpi::default()
+            },
+        );
+    }
+    Ok(spaces.get_mut(&index).unwrap())
+}
+
+impl fmt::Display for KpiSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Simulated hours: {:.1} (attended {:.1})", self.simulated_hours, self.attended_hours)?;
+        writeln!(
+            f,
+            "Comfort band: {:.1} C to {:.1} C",
+            self.comfort_band.lower, self.comfort_band.upper
+        )?;
+        writeln!(f)?;
+
+        writeln!(
+            f,
+            "{:<20} {:>14} {:>15} {:>16} {:>16} {:>16}",
+            "Space", "Heating [kWh]", "Lighting [kWh]", "Discomfort [h]", "Below [K.h]", "Above [K.h]"
+        )?;
+        for s in self.spaces.iter() {
+            writeln!(
+                f,
+                "{:<20} {:>14.2} {:>15.2} {:>16.2} {:>16.2} {:>16.2}",
+                s.name, s.heating_energy, s.lighting_energy, s.discomfort_hours, s.degree_hours_below, s.degree_hours_above
+            )?;
+        }
+        writeln!(
+            f,
+            "{:<20} {:>14.2} {:>15.2}",
+            "Total",
+            self.heating_energy(),
+            self.lighting_energy()
+        )?;
+        writeln!(f)?;
+
+        writeln!(
+            f,
+            "{:<20} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "Comfort", "Mean", "P5", "Median", "P95", "Std. dev."
+        )?;
+        for (name, s) in [("Current", &self.current_comfort), ("Potential", &self.potential_comfort)].iter() {
+            writeln!(
+                f,
+                "{:<20} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+                name, s.mean, s.p5, s.median, s.p95, s.standard_deviation
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Mean satisfaction: {:.4}", self.satisfaction.mean)?;
+        writeln!(f, "Satisfaction trend: {:.4} per day", self.satisfaction_trend)?;
+        writeln!(f)?;
+
+        writeln!(f, "{:<30} {:<20} {:>8}", "Action", "Space", "Count")?;
+        for a in self.actions.iter() {
+            writeln!(f, "{:<30} {:<20} {:>8}", a.action, a.space, a.count)?;
+        }
+        writeln!(f, "{:<30} {:<20} {:>8}", "Total", "", self.total_actions())
+    }
+}
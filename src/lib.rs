@@ -9,12 +9,82 @@ use people::people::People;
 use multiphysics_model::multiphysics_model::MultiphysicsModel;
 use weather::Weather;
 use simple_results::{SimulationResults, TimeStepResults};
+use serde::{Deserialize, Serialize};
 
+use crate::forecast::{ForecastConfig, ForecastWeather};
+use crate::overrides::StateOverrides;
+use crate::replay::StateReplay;
+use crate::rng::Rng;
 
+/// The random stream (see `Rng::derive`) of the weather forecast
+const FORECAST_STREAM: u64 = 1;
+
+pub mod results_json;
+pub mod csv_export;
+pub mod statistics;
+pub mod state_elements;
+pub mod metering;
+pub mod kpi;
+pub mod comparison;
+pub mod case;
+pub mod runner;
+pub mod batch;
+pub mod rng;
+pub mod monte_carlo;
+pub mod sensitivity;
+pub mod sweep;
+pub mod optimisation;
+pub mod date_utils;
+pub mod weather_sources;
+pub mod epw_file;
+pub mod morphing;
+pub mod weather_events;
+pub mod interpolation;
+pub mod forecast;
+pub mod weather_validation;
+pub mod hashing;
+pub mod metadata;
+pub mod cache;
+pub mod energy_balance;
+pub mod convergence;
+pub mod replay;
+pub mod measured_data;
+pub mod calibration;
+pub mod overrides;
+
+
+
+/// Options that change how `run` drives the simulation
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RunOptions {
+    /// The seed of every random number drawn by this crate during the
+    /// simulation (currently, the errors of the forecast). The people
+    /// model takes no seed, so runs with the same inputs and seed give
+    /// identical results only as long as it is deterministic.
+    #[serde(default)]
+    pub seed: u64,
+
+    /// If given, the occupants see an imperfect forecast of the
+    /// weather instead of the one that drives the physics
+    #[serde(default)]
+    pub forecast: Option<ForecastConfig>,
+
+    /// Values that replace the simulated ones of some elements of the
+    /// state, after the physics march and before the person acts. The
+    /// elements overridden in each timestep are listed in its results.
+    #[serde(default)]
+    pub overrides: Option<StateOverrides>,
+}
 
 /// This function drives the simulation, after having parsed and built
 /// the Building, State and Peoeple.
 pub fn run(start: Date, end: Date, person: &dyn People, building: &mut Building, state: &mut SimulationState, weather: &dyn Weather, n: usize)->Result<SimulationResults,String>{
+    run_with_options(start, end, person, building, state, weather, n, &RunOptions::default())
+}
+
+/// Like `run`, but with some `RunOptions`
+#[allow(clippy::too_many_arguments)]
+pub fn run_with_options(start: Date, end: Date, person: &dyn People, building: &mut Building, state: &mut SimulationState, weather: &dyn Weather, n: usize, options: &RunOptions)->Result<SimulationResults,String>{
     
     
     if start == end || start.is_later(end) {
@@ -34,6 +104,9 @@ pub fn run(start: Date, end: Date, person: &dyn People, building: &mut Building,
     let dt = 60. * 60. / n as f64;
     let sim_period = DateFactory::new(start, end, dt);
         
+    // Each stochastic component gets its own stream
+    let forecast_seed = Rng::derive(options.seed, FORECAST_STREAM).next_u64();
+
     // TODO: Calculate the capacity needed for the results
     let mut results = SimulationResults::new();
     
@@ -53,14 +126,23 @@ pub fn run(start: Date, end: Date, person: &dyn People, building: &mut Building,
         //let current_weather = weather.get_weather_data(date);
         
         // Make the model march
-        match model.march(date, weather, building, state ) {
-            Ok(_)=>{},
-            Err(e) => panic!(e)
-        }
+        model.march(date, weather, building, state)?;
         
         
-        // Control the building or person, if needed        
-        let person_result = person.control(date, weather, building, &model, state);
+        // Prescribe the values that are not simulated
+        if let Some(overrides) = &options.overrides {
+            let overridden = overrides.apply(date, state)?;
+            step_results.controllers.insert(format!("overrides"), overridden);
+        }
+
+        // Control the building or person, if needed. Occupants
+        // may see a forecast instead of the actual weather.
+        let forecast = options.forecast.as_ref().map(|f| ForecastWeather::new(weather, f, date, forecast_seed));
+        let occupant_weather: &dyn Weather = match &forecast {
+            Some(f) => f,
+            None => weather,
+        };
+        let person_result = person.control(date, occupant_weather, building, &model, state);
         step_results.controllers.insert(format!("person"), person_result);
 
         // push results
@@ -71,5 +153,41 @@ pub fn run(start: Date, end: Date, person: &dyn People, building: &mut Building,
     
 }
 
+/// Like `run`, but the building is operated by replaying the state of the
+/// windows, heaters and lights of a previous run (see `StateReplay`)
+/// instead of by a person. This allows comparing building variants under
+/// identical operation.
+pub fn run_state_replay(start: Date, end: Date, replay: &mut StateReplay, building: &mut Building, state: &mut SimulationState, weather: &dyn Weather, n: usize)->Result<SimulationResults,String>{
+    if start == end || start.is_later(end) {
+        return Err(format!("Time period inconsistency... Start = {} | End = {}", start, end));
+    }
+
+    let model = MultiphysicsModel::new(&building, state, n)?;
+    building.map_simulation_state(state)?;
+
+    let dt = 60. * 60. / n as f64;
+    let sim_period = DateFactory::new(start, end, dt);
+
+    let mut results = SimulationResults::new();
+    for date in sim_period {
+        let mut step_results = TimeStepResults{
+            timestep_start : date,
+            state_elements : state.elements().clone(),
+            weather : weather.get_weather_data(date),
+            controllers: HashMap::new()
+        };
+
+        model.march(date, weather, building, state)?;
+
+        // Set the recorded state where the person would act
+        let replay_result = replay.control(date, state)?;
+        step_results.controllers.insert(format!("replay"), replay_result);
+
+        results.push(step_results);
+    }
+
+    Ok(results)
+}
+
 
 
@@ -0,0 +1,447 @@
+use std::fmt;
+
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::case::BuildingParameters;
+use crate::date_utils::{days_in_month, hour_of_year};
+use crate::results_json::{timestep_start, timesteps};
+use crate::state_elements::{space_values, FENESTRATION_OPEN_FRACTION, SPACE_DRY_BULB_TEMPERATURE};
+
+/// Windows open by more than this fraction count as open when
+/// comparing them with the state (open or closed) of a window sensor
+pub const OPEN_WINDOW_THRESHOLD: f64 = 0.01;
+
+/// What a sensor measures
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorKind {
+    /// The air temperature of a space, in C
+    Temperature,
+
+    /// The state of the window of a space, from 0 (closed) to 1 (open)
+    Window,
+}
+
+impl SensorKind {
+    /// The state element simulating the measured variable
+    pub fn state_element(&self) -> &'static str {
+        match self {
+            SensorKind::Temperature => SPACE_DRY_BULB_TEMPERATURE,
+            SensorKind::Window => FENESTRATION_OPEN_FRACTION,
+        }
+    }
+}
+
+/// A column of the measurements, and the space where it was measured
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sensor {
+    /// The column (starting from 0)
+    pub column: usize,
+    pub kind: SensorKind,
+
+    /// The name of the space (e.g. `Bedroom 1`)
+    pub space: String,
+}
+
+impl Sensor {
+    /// The index of the state element that simulates this sensor
+    pub fn element_index(&self, building: &BuildingParameters) -> Result<usize, String> {
+        let space = match building.space_geometry().iter().position(|g| g.name == self.space) {
+            Some(v) => v,
+            None => return Err(format!("Unknown space '{}'", self.space)),
+        };
+        match self.kind {
+            SensorKind::Temperature => Ok(space),
+            SensorKind::Window => match building.window_indices()[space] {
+                Some(v) => Ok(v),
+                None => Err(format!("Space '{}' has no window", self.space)),
+            },
+        }
+    }
+
+    /// A short description, for reports
+    pub fn name(&self) -> String {
+        let kind = match self.kind {
+            SensorKind::Temperature => "temperature",
+            SensorKind::Window => "window",
+        };
+        format!("{} {}", self.space, kind)
+    }
+}
+
+/// How to read a CSV file of measurements, such as
+///
+/// ```json
+/// {
+///     "file": "apartment_3.csv",
+///     "columns": {"month": 0, "day": 1, "hour": 2},
+///     "sensors": [
+///         {"column": 3, "kind": "temperature", "space": "Bedroom 1"},
+///         {"column": 4, "kind": "window", "space": "Bedroom 1"},
+///         {"column": 5, "kind": "temperature", "space": "Living room"}
+///     ]
+/// }
+/// ```
+///
+/// Empty fields and fields that are not numbers (e.g. `NA`) are
+/// taken as missing measurements.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeasuredDataConfig {
+    pub file: String,
+
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+
+    /// Number of lines to skip at the beginning of the file
+    #[serde(default = "default_header_rows")]
+    pub header_rows: usize,
+
+    pub columns: DateColumns,
+    pub sensors: Vec<Sensor>,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_header_rows() -> usize {
+    1
+}
+
+/// The columns (starting from 0) holding the date of each measurement
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DateColumns {
+    pub month: usize,
+    pub day: usize,
+
+    /// Hour of the day, from 0 to 24
+    pub hour: usize,
+}
+
+/// The measurements of a sensor
+#[derive(Clone, Debug)]
+pub struct MeasuredSeries {
+    pub sensor: Sensor,
+
+    /// The measurements, sorted by date
+    pub values: Vec<(Date, f64)>,
+}
+
+/// Measured time series from a real building
+#[derive(Clone, Debug)]
+pub struct MeasuredData {
+    pub series: Vec<MeasuredSeries>,
+}
+
+impl MeasuredData {
+    pub fn from_file(config: &MeasuredDataConfig) -> Result<Self, String> {
+        let content = match std::fs::read_to_string(&config.file) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", config.file, e)),
+        };
+        if config.sensors.is_empty() {
+            return Err(format!("No sensors given for '{}'", config.file));
+        }
+
+        let mut series: Vec<MeasuredSeries> = config
+            .sensors
+            .iter()
+            .map(|sensor| MeasuredSeries {
+                sensor: sensor.clone(),
+                values: Vec::new(),
+            })
+            .collect();
+
+        let cols = &config.columns;
+        for (line_index, line) in content.lines().enumerate().skip(config.header_rows) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(config.delimiter).map(|f| f.trim()).collect();
+            let get = |col: usize| -> Option<f64> { fields.get(col).and_then(|f| f.parse::<f64>().ok()) };
+
+            let date = match (get(cols.month), get(cols.day), get(cols.hour)) {
+                (Some(month), Some(day), Some(hour)) => Date {
+                    month: month as usize,
+                    day: day as usize,
+                    hour,
+                },
+                _ => return Err(format!("Could not read the date in line {} of '{}'", line_index + 1, config.file)),
+            };
+            let valid = date.month >= 1
+                && date.month <= 12
+                && date.day >= 1
+                && date.day <= days_in_month(date.month)
+                && date.hour >= 0.0
+                && date.hour <= 24.0;
+            if !valid {
+                return Err(format!(
+                    "Invalid date in line {} of '{}': month {}, day {}, hour {}",
+                    line_index + 1,
+                    config.file,
+                    date.month,
+                    date.day,
+                    date.hour
+                ));
+            }
+
+            for s in series.iter_mut() {
+                if let Some(v) = get(s.sensor.column).filter(|v| v.is_finite()) {
+                    s.values.push((date, v));
+                }
+            }
+        }
+
+        for s in series.iter_mut() {
+            s.values.sort_by(|(a, _), (b, _)| {
+                hour_of_year(a)
+                    .partial_cmp(&hour_of_year(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+        if series.iter().all(|s| s.values.is_empty()) {
+            return Err(format!("No measurements found in '{}'", config.file));
+        }
+        Ok(MeasuredData { series })
+    }
+
+    /// The first and last dates with measurements
+    pub fn period(&self) -> Option<(Date, Date)> {
+        let mut dates: Vec<Date> = self
+            .series
+            .iter()
+            .flat_map(|s| s.values.first().into_iter().chain(s.values.last()))
+            .map(|(d, _)| *d)
+            .collect();
+        dates.sort_by(|a, b| {
+            hour_of_year(a)
+                .partial_cmp(&hour_of_year(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Some((*dates.first()?, *dates.last()?))
+    }
+}
+
+/// How well a simulated series matches a measured one, following
+/// ASHRAE Guideline 14
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GoodnessOfFit {
+    /// Number of measurements compared
+    pub count: usize,
+
+    /// Root mean square error, in the units of the measurements
+    pub rmse: f64,
+
+    /// Coefficient of variation of the RMSE, in %
+    pub cv_rmse: f64,
+
+    /// Normalised mean bias error (measured minus simulated), in %
+    pub nmbe: f64,
+
+    /// For window sensors, the fraction of measurements where the
+    /// simulated window is in the measured state (open or closed)
+    pub agreement: Option<f64>,
+}
+
+impl GoodnessOfFit {
+    /// Compares `(measured, simulated)` pairs. Without pairs, every
+    /// metric is NaN; with less than two, or with a measured mean of
+    /// zero, only the CV(RMSE) and NMBE are.
+    pub fn new(pairs: &[(f64, f64)]) -> Self {
+        let n = pairs.len();
+        if n == 0 {
+            return GoodnessOfFit {
+                count: 0,
+                rmse: std::f64::NAN,
+                cv_rmse: std::f64::NAN,
+                nmbe: std::f64::NAN,
+                agreement: None,
+            };
+        }
+        let mean = pairs.iter().map(|(m, _)| m).sum::<f64>() / n as f64;
+        let squared: f64 = pairs.iter().map(|(m, s)| (m - s).powi(2)).sum();
+        let bias: f64 = pairs.iter().map(|(m, s)| m - s).sum();
+
+        let (cv_rmse, nmbe) = if n > 1 && mean.abs() > 1e-9 {
+            (
+                100. * (squared / (n - 1) as f64).sqrt() / mean,
+                100. * bias / ((n - 1) as f64 * mean),
+            )
+        } else {
+            (std::f64::NAN, std::f64::NAN)
+        };
+        GoodnessOfFit {
+            count: n,
+            rmse: (squared / n as f64).sqrt(),
+            cv_rmse,
+            nmbe,
+            agreement: None,
+        }
+    }
+
+    /// Like `new`, but also compares the state (open or closed) of windows
+    pub fn for_windows(pairs: &[(f64, f64)]) -> Self {
+        let is_open = |v: f64| v > OPEN_WINDOW_THRESHOLD;
+        let agreeing = pairs.iter().filter(|(m, s)| is_open(*m) == is_open(*s)).count();
+        GoodnessOfFit {
+            agreement: if pairs.is_empty() {
+                None
+            } else {
+                Some(agreeing as f64 / pairs.len() as f64)
+            },
+            ..Self::new(pairs)
+        }
+    }
+}
+
+/// The fit of a sensor
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SensorFit {
+    pub sensor: Sensor,
+    pub fit: GoodnessOfFit,
+}
+
+/// Compares measurements with the JSON representation of some
+/// `SimulationResults`. Simulated values are interpolated linearly
+/// between timesteps, and measurements outside the simulated
+/// period are ignored. Window sensors are also compared by state
+/// (see `GoodnessOfFit::for_windows`).
+pub fn compare(measured: &MeasuredData, results: &Value, building: &BuildingParameters) -> Result<Vec<SensorFit>, String> {
+    let data = timesteps(results)?;
+    let mut fits = Vec::with_capacity(measured.series.len());
+    for series in measured.series.iter() {
+        let index = series.sensor.element_index(building)?;
+        let variant = series.sensor.kind.state_element();
+
+        let mut simulated = Vec::with_capacity(data.len());
+        for tstep in data {
+            let value = space_values(&tstep["state_elements"], variant)
+                .into_iter()
+                .find(|(i, _)| *i == index)
+                .map(|(_, v)| v);
+            if let Some(v) = value {
+                simulated.push((hour_of_year(&timestep_start(tstep)?), v));
+            }
+        }
+
+        let pairs: Vec<(f64, f64)> = series
+            .values
+            .iter()
+            .filter_map(|(date, m)| interpolate(&simulated, hour_of_year(date)).map(|s| (*m, s)))
+            .collect();
+        let fit = match series.sensor.kind {
+            SensorKind::Temperature => GoodnessOfFit::new(&pairs),
+            SensorKind::Window => GoodnessOfFit::for_windows(&pairs),
+        };
+        fits.push(SensorFit {
+            sensor: series.sensor.clone(),
+            fit,
+        });
+    }
+    Ok(fits)
+}
+
+/// The value of a sorted `(time, value)` series at a certain time,
+/// if it is within the series
+fn interpolate(series: &[(f64, f64)], t: f64) -> Option<f64> {
+    let i = series.partition_point(|(time, _)| *time < t);
+    let (t1, v1) = *series.get(i)?;
+    if (t1 - t).abs() < 1e-9 {
+        return Some(v1);
+    }
+    if i == 0 {
+        return None;
+    }
+    let (t0, v0) = series[i - 1];
+    Some(v0 + (v1 - v0) * (t - t0) / (t1 - t0))
+}
+
+impl fmt::Display for SensorFit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let agreement = match self.fit.agreement {
+            Some(v) => format!("{:.1}", 100. * v),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "{:<28} {:>8} {:>10.3} {:>10.2} {:>10.2} {:>10}",
+            self.sensor.name(),
+            self.fit.count,
+            self.fit.rmse,
+            self.fit.cv_rmse,
+            self.fit.nmbe,
+            agreement
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn perfect_fit() {
+        let fit = GoodnessOfFit::new(&[(20.0, 20.0), (22.0, 22.0), (24.0, 24.0)]);
+        assert_eq!(fit.count, 3);
+        assert!(close(fit.rmse, 0.0));
+        assert!(close(fit.cv_rmse, 0.0));
+        assert!(close(fit.nmbe, 0.0));
+        assert_eq!(fit.agreement, None);
+    }
+
+    #[test]
+    fn constant_offset() {
+        // Simulated 1 K below the measurements, whose mean is 20 C
+        let fit = GoodnessOfFit::new(&[(19.0, 18.0), (21.0, 20.0)]);
+        assert!(close(fit.rmse, 1.0));
+        // sqrt(2 / (2 - 1)) / 20 and 2 / ((2 - 1) * 20)
+        assert!(close(fit.cv_rmse, 100. * 2f64.sqrt() / 20.));
+        assert!(close(fit.nmbe, 10.0));
+    }
+
+    #[test]
+    fn too_few_pairs() {
+        let fit = GoodnessOfFit::new(&[]);
+        assert_eq!(fit.count, 0);
+        assert!(fit.rmse.is_nan());
+
+        let fit = GoodnessOfFit::new(&[(20.0, 21.0)]);
+        assert!(close(fit.rmse, 1.0));
+        assert!(fit.cv_rmse.is_nan());
+        assert!(fit.nmbe.is_nan());
+    }
+
+    #[test]
+    fn zero_mean() {
+        // Always-closed windows: no CV(RMSE), but still an agreement
+        let fit = GoodnessOfFit::for_windows(&[(0.0, 0.0), (0.0, 0.5), (0.0, 0.0), (0.0, 0.0)]);
+        assert!(fit.cv_rmse.is_nan());
+        assert!(fit.nmbe.is_nan());
+        assert!(close(fit.rmse, 0.25));
+        assert_eq!(fit.agreement, Some(0.75));
+    }
+
+    #[test]
+    fn window_states() {
+        // Partly open windows count as open
+        let fit = GoodnessOfFit::for_windows(&[(1.0, 0.3), (1.0, 0.0), (0.0, 0.0), (0.0, 0.001)]);
+        assert_eq!(fit.agreement, Some(0.75));
+        assert_eq!(GoodnessOfFit::for_windows(&[]).agreement, None);
+    }
+
+    #[test]
+    fn interpolation() {
+        let series = [(0.0, 10.0), (1.0, 20.0), (2.0, 40.0)];
+        assert_eq!(interpolate(&series, 0.0), Some(10.0));
+        assert_eq!(interpolate(&series, 1.0), Some(20.0));
+        assert!(close(interpolate(&series, 1.5).unwrap(), 30.0));
+        assert_eq!(interpolate(&series, -0.5), None);
+        assert_eq!(interpolate(&series, 2.5), None);
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::case::CaseDefinition;
+use crate::results_json::with_metadata;
+
+/// A summary of the simulated building
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BuildingSummary {
+    /// The names of the spaces
+    pub spaces: Vec<String>,
+
+    pub window_to_wall_ratio: f64,
+}
+
+/// Where some results came from
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Metadata {
+    pub crate_name: String,
+    pub crate_version: String,
+
+    /// The name of the case (e.g. `case1`)
+    pub case: String,
+
+    /// The parameters of the occupant and the building, and the run options
+    pub definition: CaseDefinition,
+
+    /// The weather, as given (an EPW file or a JSON weather specification)
+    pub weather: Option<String>,
+
+    /// A hash of the weather specification and every file it reads
+    pub weather_hash: Option<String>,
+
+    pub start: String,
+    pub end: String,
+    pub steps_per_hour: usize,
+    pub seed: u64,
+
+    pub building: BuildingSummary,
+
+    /// Wall-clock time taken by the simulation, in seconds
+    pub wall_time: f64,
+
+    pub host: String,
+
+    /// When the results were produced, in seconds since the Unix epoch
+    pub created: u64,
+}
+
+/// The name of the machine running the simulation
+pub fn host_name() -> String {
+    if let Ok(v) = std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")) {
+        if !v.is_empty() {
+            return v;
+        }
+    }
+    match std::fs::read_to_string("/etc/hostname") {
+        Ok(v) if !v.trim().is_empty() => v.trim().to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Metadata {
+    /// The metadata of a simulation that has just finished; the weather is filled in later
+    pub fn new(
+        definition: &CaseDefinition,
+        (start, end): (calendar::date::Date, calendar::date::Date),
+        steps_per_hour: usize,
+        building: BuildingSummary,
+        wall_time: f64,
+    ) -> Self {
+        Metadata {
+            crate_name: env!("CARGO_PKG_NAME").to_string(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            case: definition.case.name().to_string(),
+            definition: definition.clone(),
+            weather: None,
+            weather_hash: None,
+            start: format!("{}", start),
+            end: format!("{}", end),
+            steps_per_hour,
+            seed: definition.options.seed,
+            building,
+            wall_time,
+            host: host_name(),
+            created: unix_time(),
+        }
+    }
+
+    pub fn to_value(&self) -> Result<Value, String> {
+        match serde_json::to_value(self) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(format!("Could not serialize metadata: {}", e)),
+        }
+    }
+
+    /// Wraps some JSON content (e.g. the results) together with the metadata
+    pub fn wrap(&self, content: Value) -> Result<Value, String> {
+        Ok(with_metadata(content, self.to_value()?))
+    }
+
+    /// The name of the file holding the metadata of another file
+    pub fn sidecar_name(filename: &str) -> String {
+        format!("{}.meta.json", filename)
+    }
+
+    /// Writes the metadata next to a file that cannot hold it (e.g. a CSV
+    /// or text file), into `<filename>.meta.json`, returning its name.
+    /// The file itself is left untouched, so plain readers still work.
+    pub fn write_sidecar(&self, filename: &str) -> Result<String, String> {
+        let sidecar = Self::sidecar_name(filename);
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize metadata: {}", e)),
+        };
+        match std::fs::write(&sidecar, content) {
+            Ok(_) => Ok(sidecar),
+            Err(e) => Err(format!("Could not write file '{}': {}", sidecar, e)),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use building_model::building::Building;
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use simple_results::SimulationResults;
+
+use crate::csv_export::{column_name, write_file, CsvDialect};
+use crate::results_json::*;
+use crate::state_elements::*;
+
+/// The end uses that are metered
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EndUse {
+    HeatingCooling,
+    Lighting,
+}
+
+impl EndUse {
+    /// All the metered end uses
+    pub fn all() -> [EndUse; 2] {
+        [EndUse::HeatingCooling, EndUse::Lighting]
+    }
+
+    /// The state element variant holding the power of this end use
+    fn state_element(&self) -> &'static str {
+        match self {
+            EndUse::HeatingCooling => SPACE_HEATING_COOLING_POWER,
+            EndUse::Lighting => SPACE_LIGHTING_POWER,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EndUse::HeatingCooling => "Heating/Cooling",
+            EndUse::Lighting => "Lighting",
+        }
+    }
+}
+
+/// The period over which the readings of meters are added
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    Hourly,
+    Daily,
+    Total,
+}
+
+impl Aggregation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregation::Hourly => "hourly",
+            Aggregation::Daily => "daily",
+            Aggregation::Total => "total",
+        }
+    }
+
+    /// The beginning of the period containing a date
+    fn period_start(&self, date: Date, first: Date) -> Date {
+        match self {
+            Aggregation::Hourly => Date {
+                month: date.month,
+                day: date.day,
+                hour: date.hour.floor(),
+            },
+            Aggregation::Daily => Date {
+                month: date.month,
+                day: date.day,
+                hour: 0.0,
+            },
+            Aggregation::Total => first,
+        }
+    }
+}
+
+/// Integrates the power of the elements of one end use in one
+/// space over each timestep
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Meter {
+    /// The index of the space
+    pub space: usize,
+
+    /// The name of the space
+    pub space_name: String,
+
+    pub end_use: EndUse,
+
+    /// Energy consumed during each timestep, in Wh
+    pub readings: Vec<f64>,
+}
+
+impl Meter {
+    /// Energy consumed in the whole run, in kWh
+    pub fn total(&self) -> f64 {
+        self.readings.iter().sum::<f64>() / 1000.
+    }
+}
+
+/// The meters of all the heating/cooling and luminaire elements in a building
+#[derive(Clone, Debug)]
+pub struct Meters {
+    /// The start of each timestep
+    pub dates: Vec<Date>,
+
+    pub meters: Vec<Meter>,
+}
+
+impl Meters {
+    /// Meters some `SimulationResults`, obtained with `n` timesteps per hour
+    pub fn new(results: &SimulationResults, building: &Building, n: usize) -> Result<Self, String> {
+        let data = match serde_json::to_value(results) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize results: {}", e)),
+        };
+        Self::from_json(&data, building, n)
+    }
+
+    /// Meters the JSON representation of some `SimulationResults`, obtained
+    /// with `n` timesteps per hour.
+    ///
+    /// The power reported at the beginning of each timestep is assumed
+    /// to be held constant until the next one.
+    pub fn from_json(data: &Value, building: &Building, n: usize) -> Result<Self, String> {
+        let data = timesteps(data)?;
+        let dt = 1. / n as f64; // hours
+
+        let mut dates = Vec::with_capacity(data.len());
+        let mut meters: Vec<Meter> = Vec::new();
+
+        for (i, tstep) in data.iter().enumerate() {
+            dates.push(timestep_start(tstep)?);
+            let elements = tstep.get("state_elements").unwrap_or(&Value::Null);
+
+            for end_use in EndUse::all().iter() {
+                for (space, power) in space_values(elements, end_use.state_element()) {
+                    let meter_index = match meters.iter().position(|m| m.space == space && m.end_use == *end_use) {
+                        Some(v) => v,
+                        None => {
+                            meters.push(Meter {
+                                space,
+                                space_name: building.get_space(space)?.name().clone(),
+                                end_use: *end_use,
+                                readings: vec![0.0; data.len()],
+                            });
+                            meters.len() - 1
+                        }
+                    };
+                    meters[meter_index].readings[i] += power * dt;
+                }
+            }
+        }
+
+        meters.sort_by(|a, b| (a.space, a.end_use as usize).cmp(&(b.space, b.end_use as usize)));
+        Ok(Meters { dates, meters })
+    }
+
+    /// Adds the readings of the meters that pass a filter, aggregating them
+    /// over a period. Returns the start of each period and the energy
+    /// consumed during it, in kWh.
+    pub fn aggregate<F: Fn(&Meter) -> bool>(&self, filter: F, aggregation: Aggregation) -> Vec<(Date, f64)> {
+        let mut ret: Vec<(Date, f64)> = Vec::new();
+        let first = match self.dates.first() {
+            Some(v) => *v,
+            None => return ret,
+        };
+
+        for (i, date) in self.dates.iter().enumerate() {
+            let period = aggregation.period_start(*date, first);
+            let energy: f64 = self.meters.iter().filter(|m| filter(m)).map(|m| m.readings[i]).sum::<f64>() / 1000.;
+
+            match ret.last_mut() {
+                Some((last_period, total)) if *last_period == period => *total += energy,
+                _ => ret.push((period, energy)),
+            }
+        }
+        ret
+    }
+
+    /// Energy consumed in a space, in kWh
+    pub fn by_space(&self, space: usize, aggregation: Aggregation) -> Vec<(Date, f64)> {
+        self.aggregate(|m| m.space == space, aggregation)
+    }
+
+    /// Energy consumed by an end use, in kWh
+    pub fn by_end_use(&self, end_use: EndUse, aggregation: Aggregation) -> Vec<(Date, f64)> {
+        self.aggregate(|m| m.end_use == end_use, aggregation)
+    }
+
+    /// Energy consumed by the whole building, in kWh
+    pub fn building(&self, aggregation: Aggregation) -> Vec<(Date, f64)> {
+        self.aggregate(|_| true, aggregation)
+    }
+
+    /// Energy consumed by an end use in a space during the whole run, in kWh
+    pub fn total(&self, space: usize, end_use: EndUse) -> f64 {
+        self.meters
+            .iter()
+            .filter(|m| m.space == space && m.end_use == end_use)
+            .map(|m| m.total())
+            .sum()
+    }
+
+    /// Writes a CSV file with one column per meter, one per end use and one
+    /// for the whole building
+    pub fn write_csv(&self, filename: &str, aggregation: Aggregation, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header = vec!["Timestamp".to_string()];
+        let mut columns: Vec<Vec<(Date, f64)>> = Vec::new();
+
+        for meter in self.meters.iter() {
+            header.push(dialect.text(&column_name(
+                &format!("{} - {}", meter.end_use.name(), meter.space_name),
+                "kWh",
+            )));
+            columns.push(self.aggregate(|m| m.space == meter.space && m.end_use == meter.end_use, aggregation));
+        }
+        for end_use in EndUse::all().iter() {
+            header.push(dialect.text(&column_name(&format!("{} - Building", end_use.name()), "kWh")));
+            columns.push(self.by_end_use(*end_use, aggregation));
+        }
+        header.push(column_name("Total - Building", "kWh"));
+        columns.push(self.building(aggregation));
+
+        let n_rows = columns.last().map(|c| c.len()).unwrap_or(0);
+        let mut rows = Vec::with_capacity(n_rows);
+        for i in 0..n_rows {
+            let mut row = vec![dialect.timestamp(&columns[0][i].0)];
+            for c in columns.iter() {
+                row.push(dialect.number(c[i].1));
+            }
+            rows.push(row);
+        }
+
+        write_file(filename, &header, &rows, dialect)
+    }
+}
+
+/// Energy consumed by all the metered elements in a timestep (i.e. an
+/// element of the JSON representation of `SimulationResults`) lasting
+/// `dt` hours, in kWh
+pub fn timestep_energy(tstep: &Value, dt: f64) -> f64 {
+    let elements = tstep.get("state_elements").unwrap_or(&Value::Null);
+    EndUse::all()
+        .iter()
+        .map(|end_use| {
+            space_values(elements, end_use.state_element())
+                .iter()
+                .map(|(_, power)| power * dt / 1000.)
+                .sum::<f64>()
+        })
+        .sum()
+}
@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use weather::Weather;
+
+use crate::case::{Case, CaseDefinition};
+use crate::csv_export::{write_file, CsvDialect};
+use crate::kpi::KpiSummary;
+use crate::rng::{Distribution, Rng};
+use crate::runner::simulate_kpis;
+use crate::statistics::Statistics;
+
+/// The configuration of a Monte Carlo experiment, read from a JSON file such as
+///
+/// ```json
+/// {
+///     "case": "case1",
+///     "weather": "Santiago.epw",
+///     "seed": 1234,
+///     "replicates": 100,
+///     "parameters": {
+///         "proactivity": {"type": "uniform", "min": 0.0, "max": 1.0},
+///         "awareness_of_the_future": {"type": "triangular", "min": 0.5, "mode": 3.0, "max": 6.0}
+///     }
+/// }
+/// ```
+///
+/// Parameters that are not sampled keep the values of the case.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonteCarloConfig {
+    /// The name of the case (e.g. `case1`)
+    pub case: String,
+
+    /// The weather: an EPW file or a JSON weather specification
+    pub weather: String,
+
+    pub seed: u64,
+
+    /// Number of replicates
+    pub replicates: usize,
+
+    /// The distribution of each sampled parameter
+    pub parameters: BTreeMap<String, Distribution>,
+}
+
+impl MonteCarloConfig {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        let config: Self = match serde_json::from_str(&data) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not parse Monte Carlo configuration '{}': {}", filename, e)),
+        };
+        for (name, distribution) in config.parameters.iter() {
+            if let Err(e) = distribution.validate() {
+                return Err(format!("Parameter '{}' in '{}': {}", name, filename, e));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// A single replicate of a Monte Carlo experiment
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replicate {
+    pub index: usize,
+
+    /// The sampled parameters
+    pub parameters: BTreeMap<String, f64>,
+
+    /// The outcome, or the error that prevented obtaining it
+    pub kpi: Result<KpiSummary, String>,
+}
+
+/// The distribution of a KPI across replicates
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KpiDistribution {
+    pub name: String,
+    pub statistics: Statistics,
+
+    /// 95% confidence interval of the mean
+    pub mean_confidence_interval: [f64; 2],
+}
+
+/// The results of a Monte Carlo experiment
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonteCarloResults {
+    pub config: MonteCarloConfig,
+    pub replicates: Vec<Replicate>,
+    pub distributions: Vec<KpiDistribution>,
+}
+
+/// Samples the occupant parameters of every replicate. Each replicate
+/// gets its own random stream, so the samples do not depend on the
+/// number of threads used.
+pub fn sample_parameters(config: &MonteCarloConfig) -> Vec<BTreeMap<String, f64>> {
+    (0..config.replicates)
+        .map(|i| {
+            let mut rng = Rng::derive(config.seed, i as u64);
+            config
+                .parameters
+                .iter()
+                .map(|(name, dist)| (name.clone(), dist.sample(&mut rng)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Runs a Monte Carlo experiment, simulating the replicates in parallel
+pub fn run_monte_carlo(config: &MonteCarloConfig, weather: &(dyn Weather + Sync)) -> Result<MonteCarloResults, String> {
+    let case = Case::from_name(&config.case)?;
+    let names: Vec<&str> = config.parameters.keys().map(|k| k.as_str()).collect();
+    CaseDefinition::check_parameters(&names)?;
+
+    let samples = sample_parameters(config);
+    let mut definitions = Vec::with_capacity(samples.len());
+    for parameters in samples.iter() {
+        let mut definition = CaseDefinition::new(case);
+        for (name, value) in parameters.iter() {
+            definition.set(name, *value)?;
+        }
+        definitions.push(definition);
+    }
+
+    let replicates: Vec<Replicate> = samples
+        .into_iter()
+        .zip(simulate_kpis(&definitions, weather).into_iter())
+        .enumerate()
+        .map(|(index, (parameters, kpi))| Replicate { index, parameters, kpi })
+        .collect();
+
+    let distributions = KpiSummary::names()
+        .iter()
+        .map(|name| {
+            let values: Vec<f64> = replicates
+                .iter()
+                .filter_map(|r| r.kpi.as_ref().ok())
+                .filter_map(|k| k.get(name).ok())
+                .collect();
+            let statistics = Statistics::new(&values);
+            let half_width = if statistics.count > 1 {
+                1.96 * statistics.standard_deviation / (statistics.count as f64).sqrt()
+            } else {
+                0.0
+            };
+            KpiDistribution {
+                name: name.to_string(),
+                mean_confidence_interval: [statistics.mean - half_width, statistics.mean + half_width],
+                statistics,
+            }
+        })
+        .collect();
+
+    Ok(MonteCarloResults {
+        config: config.clone(),
+        replicates,
+        distributions,
+    })
+}
+
+impl MonteCarloResults {
+    /// Writes one row per replicate, with the sampled parameters and the KPIs
+    pub fn write_replicates_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header = vec!["Replicate".to_string()];
+        header.extend(self.config.parameters.keys().cloned());
+        header.extend(KpiSummary::names().iter().map(|s| s.to_string()));
+        header.push("Error".to_string());
+
+        let rows: Vec<Vec<String>> = self
+            .replicates
+            .iter()
+            .map(|r| {
+                let mut row = vec![format!("{}", r.index)];
+                row.extend(r.parameters.values().map(|v| dialect.number(*v)));
+                for name in KpiSummary::names() {
+                    row.push(match &r.kpi {
+                        Ok(k) => dialect.number(k.get(name).unwrap_or(std::f64::NAN)),
+                        Err(_) => String::new(),
+                    });
+                }
+                row.push(match &r.kpi {
+                    Ok(_) => String::new(),
+                    Err(e) => dialect.text(e),
+                });
+                row
+            })
+            .collect();
+
+        write_file(filename, &header, &rows, dialect)
+    }
+
+    /// Writes the whole experiment as JSON
+    pub fn write_json(&self, filename: &str) -> Result<(), String> {
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize Monte Carlo results: {}", e)),
+        };
+        match std::fs::write(filename, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+        }
+    }
+}
+
+impl fmt::Display for MonteCarloResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let failed = self.replicates.iter().filter(|r| r.kpi.is_err()).count();
+        writeln!(
+            f,
+            "Case {}, {} replicates ({} failed), seed {}",
+            self.config.case,
+            self.replicates.len(),
+            failed,
+            self.config.seed
+        )?;
+        writeln!(
+            f,
+            "{:<24} {:>12} {:>25} {:>12} {:>12} {:>12}",
+            "KPI", "Mean", "95% CI of mean", "P5", "Median", "P95"
+        )?;
+        for d in self.distributions.iter() {
+            writeln!(
+                f,
+                "{:<24} {:>12.4} {:>25} {:>12.4} {:>12.4} {:>12.4}",
+                d.name,
+                d.statistics.mean,
+                format!("[{:.4}, {:.4}]", d.mean_confidence_interval[0], d.mean_confidence_interval[1]),
+                d.statistics.p5,
+                d.statistics.median,
+                d.statistics.p95
+            )?;
+        }
+        Ok(())
+    }
+}
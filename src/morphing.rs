@@ -0,0 +1,335 @@
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use weather::current_weather::CurrentWeather;
+use weather::Weather;
+
+use crate::date_utils::days_in_month;
+use crate::epw_file::{self, EpwFile};
+use crate::weather_sources::dew_point;
+
+fn zeros() -> [f64; 12] {
+    [0.0; 12]
+}
+
+fn ones() -> [f64; 12] {
+    [1.0; 12]
+}
+
+/// Monthly change factors used for morphing a weather into a future
+/// climate (Belcher et al., 2005), read from a JSON file such as
+///
+/// ```json
+/// {
+///     "temperature_shift": [1.2, 1.3, 1.1, 1.0, 0.9, 0.9, 1.0, 1.1, 1.2, 1.3, 1.3, 1.2],
+///     "temperature_stretch": [0.05, 0.05, 0.04, 0.03, 0.02, 0.02, 0.02, 0.03, 0.04, 0.05, 0.05, 0.05],
+///     "relative_humidity_shift": [-2, -2, -1, -1, -1, 0, 0, -1, -1, -2, -2, -2],
+///     "solar_stretch": [1.02, 1.02, 1.01, 1.01, 1.0, 1.0, 1.0, 1.01, 1.01, 1.02, 1.02, 1.02]
+/// }
+/// ```
+///
+/// Missing factors leave the corresponding variable unchanged.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonthlyDeltas {
+    /// Change of the monthly mean dry bulb temperature, in K
+    #[serde(default = "zeros")]
+    pub temperature_shift: [f64; 12],
+
+    /// Fractional change of the deviations from the monthly mean dry
+    /// bulb temperature (i.e. of the diurnal range)
+    #[serde(default = "zeros")]
+    pub temperature_stretch: [f64; 12],
+
+    /// Change of the relative humidity, in percentage points
+    #[serde(default = "zeros")]
+    pub relative_humidity_shift: [f64; 12],
+
+    /// Factor applied to the solar radiation
+    #[serde(default = "ones")]
+    pub solar_stretch: [f64; 12],
+}
+
+impl MonthlyDeltas {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        let deltas: Self = match serde_json::from_str(&data) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not parse morphing factors '{}': {}", filename, e)),
+        };
+        if let Some(v) = deltas.solar_stretch.iter().find(|v| **v < 0.0) {
+            return Err(format!("Solar stretch factors cannot be negative (found {})", v));
+        }
+        Ok(deltas)
+    }
+
+    /// Morphs a dry bulb temperature of a certain month (1 to 12)
+    pub fn temperature(&self, month: usize, temperature: f64, monthly_mean: f64) -> f64 {
+        let i = month - 1;
+        temperature + self.temperature_shift[i] + self.temperature_stretch[i] * (temperature - monthly_mean)
+    }
+
+    /// Morphs a dry bulb temperature around the mean of its month, if known
+    fn temperature_around(&self, month: usize, temperature: f64, monthly_mean: Option<f64>) -> f64 {
+        self.temperature(month, temperature, monthly_mean.unwrap_or(temperature))
+    }
+
+    /// Morphs a relative humidity of a certain month (1 to 12)
+    pub fn relative_humidity(&self, month: usize, relative_humidity: f64) -> f64 {
+        (relative_humidity + self.relative_humidity_shift[month - 1]).max(0.0).min(100.0)
+    }
+
+    /// Morphs a solar radiation of a certain month (1 to 12)
+    pub fn solar(&self, month: usize, radiation: f64) -> f64 {
+        radiation * self.solar_stretch[month - 1]
+    }
+}
+
+/// The mean dry bulb temperature of each month of the original
+/// weather, which the temperature stretch is applied around.
+/// Months without data have no mean, and only get the shift.
+pub type MonthlyMeans = [Option<f64>; 12];
+
+/// The monthly means of the records of an EPW file
+pub fn epw_monthly_means(file: &EpwFile) -> Result<MonthlyMeans, String> {
+    file.monthly_means(epw_file::DRY_BULB_TEMPERATURE)
+}
+
+/// Morphs a value, unless it is the EPW missing value marker of its field
+fn morph_field(field: usize, value: f64, morph: impl Fn(f64) -> f64) -> f64 {
+    if epw_file::is_missing(field, value) {
+        value
+    } else {
+        morph(value)
+    }
+}
+
+/// A weather morphed into a future climate.
+///
+/// The weather given to the model holds the direct normal and diffuse
+/// horizontal radiation, from which the global horizontal radiation is
+/// derived, so scaling both by the solar stretch scales it exactly as
+/// `morph_epw` does.
+pub struct MorphedWeather {
+    weather: Box<dyn Weather + Send + Sync>,
+    deltas: MonthlyDeltas,
+    monthly_mean: MonthlyMeans,
+}
+
+impl MorphedWeather {
+    /// Morphs a weather around some monthly means. For an EPW file these
+    /// should be `epw_monthly_means`, so that the simulation sees the
+    /// same weather as the file written by `morph_epw`.
+    pub fn new(weather: Box<dyn Weather + Send + Sync>, deltas: MonthlyDeltas, monthly_mean: MonthlyMeans) -> Self {
+        MorphedWeather {
+            weather,
+            deltas,
+            monthly_mean,
+        }
+    }
+
+    /// The monthly means of a weather without records, sampled every
+    /// hour (from 0 to 23) of each day
+    pub fn sampled_means(weather: &dyn Weather) -> MonthlyMeans {
+        let mut monthly_mean = [None; 12];
+        for (i, mean) in monthly_mean.iter_mut().enumerate() {
+            let month = i + 1;
+            let mut values = Vec::new();
+            for day in 1..=days_in_month(month) {
+                for hour in 0..24 {
+                    let date = Date {
+                        month,
+                        day,
+                        hour: hour as f64,
+                    };
+                    let t = weather.get_weather_data(date).dry_bulb_temperature;
+                    if !epw_file::is_missing(epw_file::DRY_BULB_TEMPERATURE, t) {
+                        values.push(t);
+                    }
+                }
+            }
+            if !values.is_empty() {
+                *mean = Some(values.iter().sum::<f64>() / values.len() as f64);
+            }
+        }
+        monthly_mean
+    }
+}
+
+impl Weather for MorphedWeather {
+    fn get_weather_data(&self, date: Date) -> CurrentWeather {
+        let record = self.weather.get_weather_data(date);
+        let m = date.month;
+        let temperature = morph_field(epw_file::DRY_BULB_TEMPERATURE, record.dry_bulb_temperature, |t| {
+            self.deltas.temperature_around(m, t, self.monthly_mean[m - 1])
+        });
+        let relative_humidity = morph_field(epw_file::RELATIVE_HUMIDITY, record.relative_humidity, |rh| {
+            self.deltas.relative_humidity(m, rh)
+        });
+        // The dew point cannot be recalculated without both
+        let dew_point_temperature = if epw_file::is_missing(epw_file::DRY_BULB_TEMPERATURE, temperature)
+            || epw_file::is_missing(epw_file::RELATIVE_HUMIDITY, relative_humidity)
+        {
+            record.dew_point_temperature
+        } else {
+            dew_point(temperature, relative_humidity)
+        };
+        let solar = |field: usize, value: f64| morph_field(field, value, |r| self.deltas.solar(m, r));
+        CurrentWeather {
+            dry_bulb_temperature: temperature,
+            relative_humidity,
+            dew_point_temperature,
+            direct_normal_radiation: solar(epw_file::DIRECT_NORMAL_RADIATION, record.direct_normal_radiation),
+            diffuse_horizontal_radiation: solar(
+                epw_file::DIFFUSE_HORIZONTAL_RADIATION,
+                record.diffuse_horizontal_radiation,
+            ),
+            ..record
+        }
+    }
+}
+
+/// Morphs the records of an EPW file, so that the future
+/// climate can be used by other tools. Missing values are
+/// written back unchanged.
+pub fn morph_epw(file: &EpwFile, deltas: &MonthlyDeltas) -> Result<EpwFile, String> {
+    let monthly_mean = epw_monthly_means(file)?;
+
+    let mut morphed = file.clone();
+    for i in 0..file.records.len() {
+        let m = file.month(i)?;
+        let temperature = file.value(i, epw_file::DRY_BULB_TEMPERATURE)?;
+        let relative_humidity = file.value(i, epw_file::RELATIVE_HUMIDITY)?;
+        let temperature_missing = epw_file::is_missing(epw_file::DRY_BULB_TEMPERATURE, temperature);
+        let relative_humidity_missing = epw_file::is_missing(epw_file::RELATIVE_HUMIDITY, relative_humidity);
+
+        let temperature = deltas.temperature_around(m, temperature, monthly_mean[m - 1]);
+        let relative_humidity = deltas.relative_humidity(m, relative_humidity);
+        if !temperature_missing {
+            morphed.set_value(i, epw_file::DRY_BULB_TEMPERATURE, temperature, 1);
+        }
+        if !relative_humidity_missing {
+            morphed.set_value(i, epw_file::RELATIVE_HUMIDITY, relative_humidity, 0);
+        }
+        // The dew point cannot be recalculated without both
+        if !temperature_missing && !relative_humidity_missing {
+            morphed.set_value(
+                i,
+                epw_file::DEW_POINT_TEMPERATURE,
+                dew_point(temperature, relative_humidity),
+                1,
+            );
+        }
+        for field in [
+            epw_file::GLOBAL_HORIZONTAL_RADIATION,
+            epw_file::DIRECT_NORMAL_RADIATION,
+            epw_file::DIFFUSE_HORIZONTAL_RADIATION,
+        ]
+        .iter()
+        {
+            let radiation = file.value(i, *field)?;
+            if !epw_file::is_missing(*field, radiation) {
+                morphed.set_value(i, *field, deltas.solar(m, radiation), 0);
+            }
+        }
+    }
+    Ok(morphed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deltas() -> MonthlyDeltas {
+        let mut deltas: MonthlyDeltas = serde_json::from_str("{}").unwrap();
+        deltas.temperature_shift[0] = 2.0;
+        deltas.temperature_stretch[0] = 0.5;
+        deltas.relative_humidity_shift[0] = -10.0;
+        deltas.solar_stretch[0] = 1.5;
+        deltas
+    }
+
+    fn file() -> EpwFile {
+        let mut content = String::new();
+        for _ in 0..epw_file::HEADER_LINES {
+            content.push_str("HEADER\n");
+        }
+        // temperature, relative humidity, GHI, DNI, DHI
+        for (hour, values) in [
+            [10.0, 50.0, 100.0, 200.0, 50.0],
+            [20.0, 60.0, 9999.0, 9999.0, 9999.0],
+            [99.9, 999.0, 0.0, 0.0, 0.0],
+        ]
+        .iter()
+        .enumerate()
+        {
+            let mut fields = vec!["0".to_string(); epw_file::FIELDS];
+            fields[epw_file::MONTH] = "1".to_string();
+            fields[epw_file::DAY] = "1".to_string();
+            fields[epw_file::HOUR] = (hour + 1).to_string();
+            fields[epw_file::DEW_POINT_TEMPERATURE] = "99.9".to_string();
+            for (field, value) in [
+                epw_file::DRY_BULB_TEMPERATURE,
+                epw_file::RELATIVE_HUMIDITY,
+                epw_file::GLOBAL_HORIZONTAL_RADIATION,
+                epw_file::DIRECT_NORMAL_RADIATION,
+                epw_file::DIFFUSE_HORIZONTAL_RADIATION,
+            ]
+            .iter()
+            .zip(values.iter())
+            {
+                fields[*field] = value.to_string();
+            }
+            content.push_str(&fields.join(","));
+            content.push('\n');
+        }
+        EpwFile::parse(&content).unwrap()
+    }
+
+    #[test]
+    fn means_leave_out_missing_values() {
+        let means = epw_monthly_means(&file()).unwrap();
+        assert_eq!(means[0], Some(15.0));
+        assert_eq!(means[1], None);
+    }
+
+    #[test]
+    fn morph_records() {
+        let morphed = morph_epw(&file(), &deltas()).unwrap();
+        let value = |record: usize, field: usize| morphed.value(record, field).unwrap();
+
+        // Around the mean of 15 C
+        assert!((value(0, epw_file::DRY_BULB_TEMPERATURE) - 9.5).abs() < 1e-9);
+        assert!((value(1, epw_file::DRY_BULB_TEMPERATURE) - 24.5).abs() < 1e-9);
+        assert!((value(0, epw_file::RELATIVE_HUMIDITY) - 40.0).abs() < 1e-9);
+        assert!((value(0, epw_file::GLOBAL_HORIZONTAL_RADIATION) - 150.0).abs() < 1e-9);
+        assert!((value(0, epw_file::DIRECT_NORMAL_RADIATION) - 300.0).abs() < 1e-9);
+        assert!((value(0, epw_file::DIFFUSE_HORIZONTAL_RADIATION) - 75.0).abs() < 1e-9);
+        assert!(!epw_file::is_missing(epw_file::DEW_POINT_TEMPERATURE, value(0, epw_file::DEW_POINT_TEMPERATURE)));
+    }
+
+    #[test]
+    fn missing_values_are_kept() {
+        let original = file();
+        let morphed = morph_epw(&original, &deltas()).unwrap();
+        for field in [
+            epw_file::GLOBAL_HORIZONTAL_RADIATION,
+            epw_file::DIRECT_NORMAL_RADIATION,
+            epw_file::DIFFUSE_HORIZONTAL_RADIATION,
+        ]
+        .iter()
+        {
+            assert_eq!(morphed.records[1][*field], original.records[1][*field]);
+        }
+        for field in [
+            epw_file::DRY_BULB_TEMPERATURE,
+            epw_file::RELATIVE_HUMIDITY,
+            epw_file::DEW_POINT_TEMPERATURE,
+        ]
+        .iter()
+        {
+            assert_eq!(morphed.records[2][*field], original.records[2][*field]);
+        }
+    }
+}
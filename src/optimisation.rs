@@ -0,0 +1,583 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use weather::Weather;
+
+use crate::case::{Case, CaseDefinition};
+use crate::csv_export::{write_file, CsvDialect};
+use crate::kpi::KpiSummary;
+use crate::rng::Rng;
+use crate::runner::simulate_kpis;
+use crate::sensitivity::Factor;
+use crate::weather_sources::WeatherSpec;
+
+/// Whether an objective is to be minimised or maximised
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sense {
+    Minimise,
+    Maximise,
+}
+
+/// An objective of the optimisation
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Objective {
+    /// The KPI (see `KpiSummary::get`)
+    pub kpi: String,
+    pub sense: Sense,
+}
+
+/// The configuration of an optimisation, read from a JSON file such as
+///
+/// ```json
+/// {
+///     "case": "case1",
+///     "weather": "Santiago.epw",
+///     "seed": 1234,
+///     "population": 24,
+///     "generations": 30,
+///     "variables": [
+///         {"name": "window_scale", "min": 0.5, "max": 2.0},
+///         {"name": "concrete_thickness", "min": 0.1, "max": 0.3},
+///         {"name": "heater_power", "min": 500.0, "max": 2500.0}
+///     ],
+///     "objectives": [
+///         {"kpi": "total_energy", "sense": "minimise"},
+///         {"kpi": "mean_current_comfort", "sense": "maximise"}
+///     ],
+///     "checkpoint": "optimisation_checkpoint.json"
+/// }
+/// ```
+///
+/// The state is saved into the `checkpoint` file after every
+/// generation; an interrupted optimisation resumes from it, as long as
+/// the variables, objectives, case, population, seed and weather (including
+/// the contents of its files) are the same.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OptimisationConfig {
+    /// The name of the case (e.g. `case1`)
+    pub case: String,
+
+    /// The weather: an EPW file or a JSON weather specification
+    pub weather: String,
+
+    pub seed: u64,
+
+    /// Number of individuals in each generation
+    pub population: usize,
+
+    pub generations: usize,
+    pub variables: Vec<Factor>,
+    pub objectives: Vec<Objective>,
+
+    /// File where the state of the optimisation is saved
+    #[serde(default = "default_checkpoint")]
+    pub checkpoint: String,
+}
+
+fn default_checkpoint() -> String {
+    "optimisation_checkpoint.json".to_string()
+}
+
+impl OptimisationConfig {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        match serde_json::from_str(&data) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(format!("Could not parse optimisation configuration '{}': {}", filename, e)),
+        }
+    }
+
+    fn validate(&self) -> Result<Case, String> {
+        let case = Case::from_name(&self.case)?;
+        if self.population < 4 {
+            return Err("The population must have at least 4 individuals".to_string());
+        }
+        if self.variables.is_empty() || self.objectives.is_empty() {
+            return Err("An optimisation needs at least one variable and one objective".to_string());
+        }
+        let names: Vec<&str> = self.variables.iter().map(|v| v.name.as_str()).collect();
+        CaseDefinition::check_parameters(&names)?;
+        let mut definition = CaseDefinition::new(case);
+        for v in self.variables.iter() {
+            if v.min >= v.max {
+                return Err(format!("Variable '{}' has an empty range", v.name));
+            }
+            definition.set(&v.name, v.min)?;
+        }
+        for o in self.objectives.iter() {
+            if !KpiSummary::names().contains(&o.kpi.as_str()) {
+                return Err(format!("Unknown KPI '{}'", o.kpi));
+            }
+        }
+        Ok(case)
+    }
+}
+
+/// A candidate solution
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Individual {
+    /// The values of the variables
+    pub x: Vec<f64>,
+
+    /// The values of the objectives, as reported by the KPIs
+    /// (`None` if the simulation failed)
+    pub objectives: Option<Vec<f64>>,
+
+    /// Non-domination rank (0 is the Pareto front). Not saved,
+    /// as it is recalculated when resuming.
+    #[serde(skip)]
+    pub rank: usize,
+
+    /// Crowding distance (may be infinite, so it is not saved either)
+    #[serde(skip)]
+    pub crowding: f64,
+}
+
+impl Individual {
+    /// The objectives, all transformed for minimisation. Failed
+    /// simulations and non-finite objectives are dominated by everything else.
+    fn costs(&self, objectives: &[Objective]) -> Vec<f64> {
+        match &self.objectives {
+            Some(values) if values.iter().all(|v| v.is_finite()) => values
+                .iter()
+                .zip(objectives.iter())
+                .map(|(v, o)| match o.sense {
+                    Sense::Minimise => *v,
+                    Sense::Maximise => -v,
+                })
+                .collect(),
+            _ => vec![std::f64::INFINITY; objectives.len()],
+        }
+    }
+}
+
+/// The state of an optimisation, which is saved after each generation
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OptimisationState {
+    pub config: OptimisationConfig,
+
+    /// Number of generations completed
+    pub generation: usize,
+
+    /// Number of simulations performed
+    pub evaluations: usize,
+
+    pub rng: Rng,
+    pub population: Vec<Individual>,
+
+    /// The hash of the weather (and the files it reads) that the
+    /// population was evaluated with (see `WeatherSpec::hash`)
+    #[serde(default)]
+    pub weather_hash: String,
+}
+
+impl OptimisationState {
+    /// Saves the state into the checkpoint file
+    pub fn save(&self) -> Result<(), String> {
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize optimisation state: {}", e)),
+        };
+        // Write and rename, so that an interruption does not corrupt the checkpoint
+        let tmp = format!("{}.tmp", self.config.checkpoint);
+        if let Err(e) = std::fs::write(&tmp, content) {
+            return Err(format!("Could not write file '{}': {}", tmp, e));
+        }
+        match std::fs::rename(&tmp, &self.config.checkpoint) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", self.config.checkpoint, e)),
+        }
+    }
+
+    /// Loads a state from a checkpoint file
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        match serde_json::from_str(&data) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(format!("Could not parse checkpoint '{}': {}", filename, e)),
+        }
+    }
+
+    /// The non-dominated individuals of the current population
+    pub fn pareto_front(&self) -> Vec<&Individual> {
+        self.population
+            .iter()
+            .filter(|i| i.rank == 0 && i.objectives.is_some())
+            .collect()
+    }
+
+    /// Writes the Pareto front, with the variables that produced it
+    pub fn write_pareto_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header: Vec<String> = self.config.variables.iter().map(|v| v.name.clone()).collect();
+        header.extend(self.config.objectives.iter().map(|o| o.kpi.clone()));
+
+        let rows: Vec<Vec<String>> = self
+            .pareto_front()
+            .iter()
+            .map(|i| {
+                let mut row: Vec<String> = i.x.iter().map(|v| dialect.number(*v)).collect();
+                if let Some(obj) = &i.objectives {
+                    row.extend(obj.iter().map(|v| dialect.number(*v)));
+                }
+                row
+            })
+            .collect();
+        write_file(filename, &header, &rows, dialect)
+    }
+}
+
+/// Simulates a set of individuals, returning the number of simulations
+fn evaluate(case: Case, config: &OptimisationConfig, individuals: &mut [Individual], weather: &(dyn Weather + Sync)) -> Result<usize, String> {
+    let mut definitions = Vec::with_capacity(individuals.len());
+    for ind in individuals.iter() {
+        let mut definition = CaseDefinition::new(case);
+        for (var, value) in config.variables.iter().zip(ind.x.iter()) {
+            definition.set(&var.name, *value)?;
+        }
+        definitions.push(definition);
+    }
+
+    let kpis = simulate_kpis(&definitions, weather);
+    for (ind, kpi) in individuals.iter_mut().zip(kpis.iter()) {
+        ind.objectives = match kpi {
+            Ok(k) => {
+                let mut values = Vec::with_capacity(config.objectives.len());
+                for o in config.objectives.iter() {
+                    values.push(k.get(&o.kpi)?);
+                }
+                // Non-finite objectives (e.g. a trend without attended hours)
+                // are infeasible, and cannot be written into the checkpoint
+                if values.iter().all(|v| v.is_finite()) {
+                    Some(values)
+                } else {
+                    None
+                }
+            }
+            Err(_) => None,
+        };
+    }
+    Ok(individuals.len())
+}
+
+/// Whether `a` dominates `b` (all costs are minimised)
+fn dominates(a: &[f64], b: &[f64]) -> bool {
+    let mut strictly_better = false;
+    for (x, y) in a.iter().zip(b.iter()) {
+        if x > y {
+            return false;
+        }
+        if x < y {
+            strictly_better = true;
+        }
+    }
+    strictly_better
+}
+
+/// Assigns the non-domination rank and the crowding distance of
+/// every individual (i.e. NSGA-II's fast non-dominated sort)
+fn rank_population(population: &mut [Individual], objectives: &[Objective]) {
+    let n = population.len();
+    let costs: Vec<Vec<f64>> = population.iter().map(|i| i.costs(objectives)).collect();
+
+    let mut dominated_by: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut domination_count = vec![0; n];
+    for i in 0..n {
+        for j in 0..n {
+            if i != j && dominates(&costs[i], &costs[j]) {
+                dominated_by[i].push(j);
+            } else if i != j && dominates(&costs[j], &costs[i]) {
+                domination_count[i] += 1;
+            }
+        }
+    }
+
+    let mut front: Vec<usize> = (0..n).filter(|i| domination_count[*i] == 0).collect();
+    let mut rank = 0;
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for i in front.iter() {
+            population[*i].rank = rank;
+            for j in dominated_by[*i].iter() {
+                domination_count[*j] -= 1;
+                if domination_count[*j] == 0 {
+                    next.push(*j);
+                }
+            }
+        }
+
+        // Crowding distance within the front
+        for i in front.iter() {
+            population[*i].crowding = 0.0;
+        }
+        for m in 0..objectives.len() {
+            let mut sorted = front.clone();
+            sorted.sort_by(|a, b| costs[*a][m].partial_cmp(&costs[*b][m]).unwrap_or(Ordering::Equal));
+            let first = sorted[0];
+            let last = sorted[sorted.len() - 1];
+            let span = costs[last][m] - costs[first][m];
+            population[first].crowding = std::f64::INFINITY;
+            population[last].crowding = std::f64::INFINITY;
+            if span > 0.0 && span.is_finite() {
+                for w in 1..sorted.len().saturating_sub(1) {
+                    population[sorted[w]].crowding += (costs[sorted[w + 1]][m] - costs[sorted[w - 1]][m]) / span;
+                }
+            }
+        }
+
+        front = next;
+        rank += 1;
+    }
+}
+
+/// Whether `a` is preferred over `b` (lower rank, then larger crowding distance)
+fn better(a: &Individual, b: &Individual) -> bool {
+    a.rank < b.rank || (a.rank == b.rank && a.crowding > b.crowding)
+}
+
+/// Creates the offspring of a population by binary tournament, simulated
+/// binary crossover and polynomial mutation
+fn offspring(population: &[Individual], variables: &[Factor], rng: &mut Rng) -> Vec<Individual> {
+    const ETA_CROSSOVER: f64 = 15.;
+    const ETA_MUTATION: f64 = 20.;
+    let k = variables.len();
+    let mutation_probability = 1. / k as f64;
+
+    let tournament = |rng: &mut Rng| -> usize {
+        let a = rng.index(population.len());
+        let b = rng.index(population.len());
+        if better(&population[a], &population[b]) {
+            a
+        } else {
+            b
+        }
+    };
+
+    let mut children = Vec::with_capacity(population.len());
+    while children.len() < population.len() {
+        let p1 = &population[tournament(rng)].x;
+        let p2 = &population[tournament(rng)].x;
+        let mut c1 = p1.clone();
+        let mut c2 = p2.clone();
+
+        // Simulated binary crossover
+        if rng.uniform() < 0.9 {
+            for i in 0..k {
+                if rng.uniform() > 0.5 {
+                    continue;
+                }
+                let u = rng.uniform();
+                let beta = if u <= 0.5 {
+                    (2. * u).powf(1. / (ETA_CROSSOVER + 1.))
+                } else {
+                    (1. / (2. * (1. - u))).powf(1. / (ETA_CROSSOVER + 1.))
+                };
+                c1[i] = 0.5 * ((1. + beta) * p1[i] + (1. - beta) * p2[i]);
+                c2[i] = 0.5 * ((1. - beta) * p1[i] + (1. + beta) * p2[i]);
+            }
+        }
+
+        // Polynomial mutation
+        for c in [&mut c1, &mut c2].iter_mut() {
+            for (i, var) in variables.iter().enumerate() {
+                if rng.uniform() < mutation_probability {
+                    let u = rng.uniform();
+                    let delta = if u < 0.5 {
+                        (2. * u).powf(1. / (ETA_MUTATION + 1.)) - 1.
+                    } else {
+                        1. - (2. * (1. - u)).powf(1. / (ETA_MUTATION + 1.))
+                    };
+                    c[i] += delta * (var.max - var.min);
+                }
+                c[i] = c[i].max(var.min).min(var.max);
+            }
+        }
+
+        for x in vec![c1, c2] {
+            if children.len() < population.len() {
+                children.push(Individual {
+                    x,
+                    objectives: None,
+                    rank: 0,
+                    crowding: 0.0,
+                });
+            }
+        }
+    }
+    children
+}
+
+/// Runs (or resumes, if the checkpoint file exists) a multi-objective
+/// optimisation using NSGA-II.
+pub fn run_optimisation(config: &OptimisationConfig, weather: &(dyn Weather + Sync)) -> Result<OptimisationState, String> {
+    let case = config.validate()?;
+    let weather_hash = WeatherSpec::from_arg(&config.weather)?.hash()?;
+
+    let mut state = if std::path::Path::new(&config.checkpoint).exists() {
+        let mut state = OptimisationState::load(&config.checkpoint)?;
+        let same_problem = serde_json::to_value(&state.config.variables).ok() == serde_json::to_value(&config.variables).ok()
+            && serde_json::to_value(&state.config.objectives).ok() == serde_json::to_value(&config.objectives).ok()
+            && state.config.case == config.case
+            && state.config.population == config.population;
+        if !same_problem {
+            return Err(format!(
+                "Checkpoint '{}' belongs to a different optimisation... remove it to start again",
+                config.checkpoint
+            ));
+        }
+        // Objectives evaluated under another weather or seed cannot be ranked with new ones
+        if state.weather_hash != weather_hash || state.config.seed != config.seed {
+            return Err(format!(
+                "Checkpoint '{}' was computed with a different weather or seed... remove it to start again",
+                config.checkpoint
+            ));
+        }
+        // The number of generations can be extended when resuming
+        state.config.generations = config.generations;
+        rank_population(&mut state.population, &state.config.objectives);
+        state
+    } else {
+        let mut rng = Rng::new(config.seed);
+        let mut population: Vec<Individual> = (0..config.population)
+            .map(|_| Individual {
+                x: config.variables.iter().map(|v| rng.range(v.min, v.max)).collect(),
+                objectives: None,
+                rank: 0,
+                crowding: 0.0,
+            })
+            .collect();
+        let evaluations = evaluate(case, config, &mut population, weather)?;
+        rank_population(&mut population, &config.objectives);
+        let state = OptimisationState {
+            config: config.clone(),
+            generation: 0,
+            evaluations,
+            rng,
+            population,
+            weather_hash,
+        };
+        state.save()?;
+        state
+    };
+
+    while state.generation < state.config.generations {
+        let mut children = offspring(&state.population, &state.config.variables, &mut state.rng);
+        state.evaluations += evaluate(case, &state.config, &mut children, weather)?;
+
+        let mut combined = state.population.clone();
+        combined.extend(children);
+        rank_population(&mut combined, &state.config.objectives);
+        combined.sort_by(|a, b| {
+            if better(a, b) {
+                Ordering::Less
+            } else if better(b, a) {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        });
+        combined.truncate(state.config.population);
+        rank_population(&mut combined, &state.config.objectives);
+
+        state.population = combined;
+        state.generation += 1;
+        state.save()?;
+    }
+
+    Ok(state)
+}
+
+impl fmt::Display for OptimisationState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Generation {} of {} ({} simulations)",
+            self.generation, self.config.generations, self.evaluations
+        )?;
+        for v in self.config.variables.iter() {
+            write!(f, "{:>16} ", v.name)?;
+        }
+        for o in self.config.objectives.iter() {
+            write!(f, "{:>24} ", o.kpi)?;
+        }
+        writeln!(f)?;
+        for i in self.pareto_front() {
+            for x in i.x.iter() {
+                write!(f, "{:>16.4} ", x)?;
+            }
+            if let Some(obj) = &i.objectives {
+                for v in obj.iter() {
+                    write!(f, "{:>24.4} ", v)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn individual(objectives: Option<Vec<f64>>) -> Individual {
+        Individual {
+            x: Vec::new(),
+            objectives,
+            rank: 0,
+            crowding: 0.0,
+        }
+    }
+
+    fn objectives() -> Vec<Objective> {
+        vec![
+            Objective {
+                kpi: "total_energy".to_string(),
+                sense: Sense::Minimise,
+            },
+            Objective {
+                kpi: "mean_current_comfort".to_string(),
+                sense: Sense::Maximise,
+            },
+        ]
+    }
+
+    #[test]
+    fn domination() {
+        assert!(dominates(&[1., 1.], &[2., 2.]));
+        assert!(dominates(&[1., 2.], &[2., 2.]));
+        assert!(!dominates(&[2., 2.], &[2., 2.]));
+        assert!(!dominates(&[1., 3.], &[2., 2.]));
+    }
+
+    #[test]
+    fn non_dominated_sorting() {
+        // (energy, comfort): the first two are the Pareto front
+        let mut population = vec![
+            individual(Some(vec![1., 0.5])),
+            individual(Some(vec![2., 0.9])),
+            individual(Some(vec![3., 0.4])),
+            individual(Some(vec![std::f64::NAN, 1.0])),
+            individual(None),
+        ];
+        rank_population(&mut population, &objectives());
+
+        assert_eq!(population[0].rank, 0);
+        assert_eq!(population[1].rank, 0);
+        assert_eq!(population[2].rank, 1);
+        // Non-finite and failed individuals are dominated by everything else
+        assert!(population[3].rank >= 2);
+        assert!(population[4].rank >= 2);
+
+        // The extremes of a front have infinite crowding distance
+        assert!(population[0].crowding.is_infinite() && population[1].crowding.is_infinite());
+        assert!(better(&population[0], &population[2]));
+    }
+}
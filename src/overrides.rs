@@ -0,0 +1,265 @@
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use simulation_state::simulation_state::SimulationState;
+
+use crate::case::BuildingParameters;
+use crate::date_utils::hour_of_year;
+use crate::measured_data::{MeasuredData, MeasuredDataConfig};
+use crate::state_elements::set_value;
+
+/// A prescribed value of an element
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OverrideValue {
+    pub month: usize,
+    pub day: usize,
+    pub hour: f64,
+    pub value: f64,
+}
+
+impl OverrideValue {
+    fn hour_of_year(&self) -> f64 {
+        hour_of_year(&Date {
+            month: self.month,
+            day: self.day,
+            hour: self.hour,
+        })
+    }
+}
+
+/// The values prescribed to an element of the state
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "OverrideSeries")]
+pub struct ElementOverride {
+    /// The variant of the element (e.g. `SpaceDryBulbTemperature`, see `state_elements`)
+    pub element: String,
+
+    /// The index of the space or fenestration
+    pub index: usize,
+
+    /// The values, sorted by date. Each one holds until the next (or for
+    /// `max_hold` hours, if that is shorter), and the element is simulated
+    /// before the first and after the last.
+    pub values: Vec<OverrideValue>,
+
+    /// The longest time a value holds, in hours, so that gaps in the
+    /// series are simulated instead of filled with the value before them
+    pub max_hold: Option<f64>,
+
+    /// The hour of the year of each value
+    #[serde(skip)]
+    times: Vec<f64>,
+}
+
+/// An `ElementOverride` as written in a file
+#[derive(Clone, Debug, Deserialize)]
+struct OverrideSeries {
+    element: String,
+    index: usize,
+    values: Vec<OverrideValue>,
+
+    #[serde(default)]
+    max_hold: Option<f64>,
+}
+
+impl From<OverrideSeries> for ElementOverride {
+    fn from(series: OverrideSeries) -> Self {
+        ElementOverride::new(series.element, series.index, series.values, series.max_hold)
+    }
+}
+
+impl ElementOverride {
+    /// Sorts the values by date
+    pub fn new(element: String, index: usize, mut values: Vec<OverrideValue>, max_hold: Option<f64>) -> Self {
+        values.sort_by(|a, b| {
+            a.hour_of_year()
+                .partial_cmp(&b.hour_of_year())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let times = values.iter().map(|v| v.hour_of_year()).collect();
+        ElementOverride {
+            element,
+            index,
+            values,
+            max_hold,
+            times,
+        }
+    }
+
+    /// The value prescribed for a certain date, if any
+    pub fn value(&self, date: Date) -> Option<f64> {
+        let t = hour_of_year(&date);
+
+        // The number of values up to `t`; the last of them holds
+        let i = self.times.partition_point(|time| *time <= t);
+        if i == 0 {
+            return None;
+        }
+        let held = t - self.times[i - 1];
+        if i == self.times.len() && held > 0. {
+            return None;
+        }
+        if self.max_hold.map_or(false, |max| held > max) {
+            return None;
+        }
+        self.values.get(i - 1).map(|v| v.value)
+    }
+}
+
+/// Time series that replace the simulated values of some elements of the
+/// state, read from a JSON file such as
+///
+/// ```json
+/// {
+///     "series": [
+///         {
+///             "element": "FenestrationOpenFraction",
+///             "index": 0,
+///             "values": [{"month": 7, "day": 1, "hour": 8.0, "value": 1.0}, {"month": 7, "day": 1, "hour": 9.0, "value": 0.0}],
+///             "max_hold": 2.0
+///         }
+///     ],
+///     "measured": {"file": "apartment_3.csv", "columns": {"month": 0, "day": 1, "hour": 2}, "sensors": [{"column": 3, "kind": "temperature", "space": "Bedroom 1"}]},
+///     "measured_max_hold": 1.0
+/// }
+/// ```
+///
+/// where `measured` (optional, see `MeasuredDataConfig`) adds a series per
+/// sensor, whose values hold for at most `measured_max_hold` hours (if given).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StateOverrides {
+    #[serde(default)]
+    pub series: Vec<ElementOverride>,
+}
+
+/// The contents of an overrides file
+#[derive(Clone, Debug, Deserialize)]
+struct OverridesFile {
+    #[serde(default)]
+    series: Vec<ElementOverride>,
+
+    #[serde(default)]
+    measured: Option<MeasuredDataConfig>,
+
+    #[serde(default)]
+    measured_max_hold: Option<f64>,
+}
+
+impl StateOverrides {
+    /// Reads the overrides from a file. Sensors of measured data are mapped
+    /// into the elements of the building described by `building`.
+    pub fn from_file(filename: &str, building: &BuildingParameters) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        let file: OverridesFile = match serde_json::from_str(&data) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not parse overrides '{}': {}", filename, e)),
+        };
+
+        let mut overrides = StateOverrides { series: file.series };
+        if let Some(config) = file.measured {
+            let measured = MeasuredData::from_file(&config)?;
+            overrides.series.extend(Self::from_measured(&measured, building, file.measured_max_hold)?.series);
+        }
+        Ok(overrides)
+    }
+
+    /// Prescribes the measured values of every sensor, each holding
+    /// for at most `max_hold` hours (if given)
+    pub fn from_measured(measured: &MeasuredData, building: &BuildingParameters, max_hold: Option<f64>) -> Result<Self, String> {
+        let mut series = Vec::with_capacity(measured.series.len());
+        for s in measured.series.iter() {
+            let values = s
+                .values
+                .iter()
+                .map(|(date, value)| OverrideValue {
+                    month: date.month,
+                    day: date.day,
+                    hour: date.hour,
+                    value: *value,
+                })
+                .collect();
+            series.push(ElementOverride::new(
+                s.sensor.kind.state_element().to_string(),
+                s.sensor.element_index(building)?,
+                values,
+                max_hold,
+            ));
+        }
+        Ok(StateOverrides { series })
+    }
+
+    /// Sets the prescribed values for a certain date, returning
+    /// which elements were overridden
+    pub fn apply(&self, date: Date, state: &mut SimulationState) -> Result<Value, String> {
+        let mut overridden = Vec::new();
+        for series in self.series.iter() {
+            if let Some(value) = series.value(date) {
+                set_value(state, &series.element, series.index, value)?;
+                overridden.push(json!([series.element, series.index, value]));
+            }
+        }
+        Ok(json!({ "overridden": overridden }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(max_hold: Option<f64>) -> ElementOverride {
+        let value = |hour: f64, value: f64| OverrideValue {
+            month: 7,
+            day: 1,
+            hour,
+            value,
+        };
+        // Given out of order, with a gap between 10 and 14
+        ElementOverride::new(
+            "FenestrationOpenFraction".to_string(),
+            0,
+            vec![value(9.0, 0.5), value(8.0, 1.0), value(14.0, 0.0), value(10.0, 0.2)],
+            max_hold,
+        )
+    }
+
+    fn at(hour: f64) -> Date {
+        Date { month: 7, day: 1, hour }
+    }
+
+    #[test]
+    fn holds_until_next() {
+        let s = series(None);
+        assert_eq!(s.value(at(7.5)), None);
+        assert_eq!(s.value(at(8.0)), Some(1.0));
+        assert_eq!(s.value(at(8.9)), Some(1.0));
+        assert_eq!(s.value(at(9.0)), Some(0.5));
+        assert_eq!(s.value(at(13.0)), Some(0.2));
+        assert_eq!(s.value(at(14.0)), Some(0.0));
+        assert_eq!(s.value(at(14.5)), None);
+    }
+
+    #[test]
+    fn gaps_longer_than_max_hold() {
+        let s = series(Some(1.0));
+        assert_eq!(s.value(at(9.5)), Some(0.5));
+        assert_eq!(s.value(at(11.0)), Some(0.2));
+        assert_eq!(s.value(at(11.5)), None);
+        assert_eq!(s.value(at(14.0)), Some(0.0));
+    }
+
+    #[test]
+    fn deserialized_values_are_sorted() {
+        let s: ElementOverride = serde_json::from_str(
+            r#"{"element": "SpaceDryBulbTemperature", "index": 1, "values": [
+                {"month": 7, "day": 2, "hour": 0.0, "value": 2.0},
+                {"month": 7, "day": 1, "hour": 0.0, "value": 1.0}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(s.value(at(12.0)), Some(1.0));
+        assert_eq!(s.max_hold, None);
+    }
+}
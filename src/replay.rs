@@ -0,0 +1,232 @@
+use calendar::date::Date;
+use serde_json::{json, Value};
+use simulation_state::simulation_state::SimulationState;
+
+use crate::case::BuildingParameters;
+use crate::date_utils::{date_from_hour_of_year, hour_of_year, hours_between};
+use crate::metadata::Metadata;
+use crate::results_json::{actions_taken, controller, get_bool, metadata, timestep_start, timesteps};
+use crate::state_elements::{
+    set_value, space_values, FENESTRATION_OPEN_FRACTION, SPACE_HEATING_COOLING_POWER, SPACE_LIGHTING_POWER,
+};
+
+/// The state elements operated by the person
+const OPERATED_ELEMENTS: [&str; 3] = [FENESTRATION_OPEN_FRACTION, SPACE_HEATING_COOLING_POWER, SPACE_LIGHTING_POWER];
+
+/// The name of the space of each element of an operated variant, by
+/// index. Windows are numbered skipping the spaces without one, so
+/// their indices change between building variants, but their spaces do not.
+fn element_spaces(building: &BuildingParameters, variant: &str) -> Vec<(usize, String)> {
+    let geometry = building.space_geometry();
+    if variant == FENESTRATION_OPEN_FRACTION {
+        building
+            .window_indices()
+            .iter()
+            .zip(geometry.iter())
+            .filter_map(|(window, g)| window.map(|w| (w, g.name.clone())))
+            .collect()
+    } else {
+        geometry.into_iter().enumerate().map(|(i, g)| (i, g.name)).collect()
+    }
+}
+
+/// The actions taken by the person in one timestep, and the
+/// state of the elements they operate after taking them
+#[derive(Clone, Debug)]
+pub struct LoggedStep {
+    pub date: Date,
+    pub actions: Vec<(String, String)>,
+
+    /// `(variant, space name, value)` of every operated element
+    pub operation: Vec<(String, String, f64)>,
+}
+
+/// The actions taken during a previous run, and the state of the
+/// elements they operated
+#[derive(Clone, Debug, Default)]
+pub struct ActionLog {
+    /// The steps with actions, sorted by date
+    pub steps: Vec<LoggedStep>,
+
+    /// The timesteps per hour of the recorded run
+    pub steps_per_hour: Option<usize>,
+
+    /// The period of the recorded run: the start of its first timestep
+    /// and the end of its last one
+    pub period: Option<(Date, Date)>,
+}
+
+impl ActionLog {
+    /// Builds the log from the JSON representation of some `SimulationResults`.
+    ///
+    /// The elements are identified by the name of their space, using the
+    /// building recorded in the metadata of the results (or the default one,
+    /// for results without metadata), so they can be replayed on a building
+    /// whose windows are numbered differently.
+    ///
+    /// The state of each timestep is recorded before the physics march,
+    /// so it holds the outcome of the actions taken during the previous one.
+    /// Actions taken during the last timestep have no recorded outcome,
+    /// and are left out.
+    pub fn from_results(data: &Value) -> Result<Self, String> {
+        let data_timesteps = timesteps(data)?;
+        let dates = data_timesteps
+            .iter()
+            .map(timestep_start)
+            .collect::<Result<Vec<Date>, String>>()?;
+
+        // From the metadata or, for older results, from the timesteps
+        let steps_per_hour = metadata(data)
+            .and_then(|m| m.get("steps_per_hour"))
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .or_else(|| match dates.as_slice() {
+                [first, second, ..] if hours_between(first, second) > 0. => {
+                    Some((1. / hours_between(first, second)).round() as usize)
+                }
+                _ => None,
+            });
+        let period = match (dates.first(), dates.last(), steps_per_hour) {
+            (Some(first), Some(last), Some(n)) => Some((
+                *first,
+                date_from_hour_of_year(hour_of_year(last) + 1. / n as f64),
+            )),
+            _ => None,
+        };
+
+        let recorded = metadata(data)
+            .and_then(|m| serde_json::from_value::<Metadata>(m.clone()).ok())
+            .map(|m| m.definition.building)
+            .unwrap_or_default();
+        let spaces: Vec<Vec<(usize, String)>> = OPERATED_ELEMENTS
+            .iter()
+            .map(|variant| element_spaces(&recorded, variant))
+            .collect();
+
+        let data = data_timesteps;
+        let mut steps = Vec::new();
+        for pair in data.windows(2) {
+            let person = controller(&pair[0], "person")?;
+            if !get_bool(person, "attended")? {
+                continue;
+            }
+            let actions = actions_taken(person)?;
+            if actions.is_empty() {
+                continue;
+            }
+
+            let elements = &pair[1]["state_elements"];
+            let mut operation = Vec::new();
+            for (variant, spaces) in OPERATED_ELEMENTS.iter().zip(spaces.iter()) {
+                for (index, value) in space_values(elements, variant) {
+                    let space = match spaces.iter().find(|(i, _)| *i == index) {
+                        Some((_, name)) => name.clone(),
+                        None => return Err(format!("The recorded building has no {} {}", variant, index)),
+                    };
+                    operation.push((variant.to_string(), space, value));
+                }
+            }
+            steps.push(LoggedStep {
+                date: timestep_start(&pair[0])?,
+                actions,
+                operation,
+            });
+        }
+        Ok(ActionLog {
+            steps,
+            steps_per_hour,
+            period,
+        })
+    }
+
+    /// Reads the log from a results file written by a previous run
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        let data: Value = match serde_json::from_str(&data) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not parse results '{}': {}", filename, e)),
+        };
+        Self::from_results(&data)
+    }
+
+    /// Total number of actions in the log
+    pub fn len(&self) -> usize {
+        self.steps.iter().map(|s| s.actions.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// Operates a building by replaying the state of a previous run, without a
+/// people model: at the timesteps where the person acted, the windows,
+/// heaters and lights are set to the values they had after those actions.
+///
+/// The recorded actions themselves are not performed again (their effect
+/// depends on the people model); they are only reported, for reference.
+///
+/// Elements are matched by the name of their space, so windows of spaces
+/// that have no window in the replayed building are skipped (and reported).
+pub struct StateReplay<'a> {
+    log: &'a ActionLog,
+
+    /// The index of each operated element of the replayed building, by
+    /// variant (as in `OPERATED_ELEMENTS`) and space name
+    indices: Vec<Vec<(usize, String)>>,
+
+    /// The next step of the log to replay
+    cursor: usize,
+}
+
+impl<'a> StateReplay<'a> {
+    /// Replays a log on the building described by `building`
+    pub fn new(log: &'a ActionLog, building: &BuildingParameters) -> Self {
+        let indices = OPERATED_ELEMENTS
+            .iter()
+            .map(|variant| element_spaces(building, variant))
+            .collect();
+        StateReplay { log, indices, cursor: 0 }
+    }
+
+    /// Sets the state logged for a certain date (if any), returning what
+    /// was replayed. Dates must be given in order, and match the ones in
+    /// the log, so the timesteps per hour of the replay must be those of
+    /// the recorded run.
+    pub fn control(&mut self, date: Date, state: &mut SimulationState) -> Result<Value, String> {
+        // Skip the steps left behind
+        let steps = &self.log.steps;
+        while self.cursor < steps.len() && date.is_later(steps[self.cursor].date) {
+            self.cursor += 1;
+        }
+        let step = match steps.get(self.cursor) {
+            Some(v) if v.date == date => v,
+            _ => return Ok(json!({ "replayed": false, "actions_taken": [] })),
+        };
+        self.cursor += 1;
+
+        let mut skipped = Vec::new();
+        for (variant, space, value) in step.operation.iter() {
+            let index = OPERATED_ELEMENTS
+                .iter()
+                .position(|v| v == variant)
+                .and_then(|v| self.indices[v].iter().find(|(_, name)| name == space))
+                .map(|(i, _)| *i);
+            let index = match index {
+                Some(v) => v,
+                None => {
+                    skipped.push(json!([variant, space]));
+                    continue;
+                }
+            };
+            if let Err(e) = set_value(state, variant, index, *value) {
+                return Err(format!("{}, so the log cannot be replayed on it", e));
+            }
+        }
+
+        Ok(json!({ "replayed": true, "actions_taken": step.actions, "skipped": skipped }))
+    }
+}
@@ -0,0 +1,135 @@
+use calendar::date::Date;
+use serde_json::{Map, Value};
+
+/// Reads a `Date` from its JSON representation (i.e. as it
+/// is written by `serde_json` when serializing `SimulationResults`)
+pub fn parse_date(date: &Value) -> Result<Date, String> {
+    let date = match date.as_object() {
+        Some(v) => v,
+        None => return Err(format!("Date is not an object: {}", date)),
+    };
+
+    let month = date.get("month").and_then(|v| v.as_u64());
+    let day = date.get("day").and_then(|v| v.as_u64());
+    let hour = date.get("hour").and_then(|v| v.as_f64());
+
+    match (month, day, hour) {
+        (Some(month), Some(day), Some(hour)) => Ok(Date {
+            month: month as usize,
+            day: day as usize,
+            hour: hour,
+        }),
+        _ => Err("Date must have 'month', 'day' and 'hour'".to_string()),
+    }
+}
+
+/// Returns the timesteps contained in the JSON representation
+/// of some `SimulationResults`, either bare or wrapped together
+/// with their metadata (see `with_metadata`)
+pub fn timesteps(data: &Value) -> Result<&Vec<Value>, String> {
+    match data.as_array().or_else(|| data.get("timesteps").and_then(|v| v.as_array())) {
+        Some(v) => Ok(v),
+        None => Err("Simulation results are expected to be an array of timesteps".to_string()),
+    }
+}
+
+/// Wraps the JSON representation of some `SimulationResults`
+/// together with a metadata block
+pub fn with_metadata(results: Value, metadata: Value) -> Value {
+    let mut map = Map::new();
+    map.insert("metadata".to_string(), metadata);
+    map.insert("timesteps".to_string(), results);
+    Value::Object(map)
+}
+
+/// Returns the metadata of some results, if they have any
+pub fn metadata(data: &Value) -> Option<&Value> {
+    data.get("metadata")
+}
+
+/// Returns the start of a timestep
+pub fn timestep_start(tstep: &Value) -> Result<Date, String> {
+    match tstep.get("timestep_start") {
+        Some(v) => parse_date(v),
+        None => Err("Timestep has no 'timestep_start'".to_string()),
+    }
+}
+
+/// Returns the output of a controller (e.g. "person") in a timestep
+pub fn controller<'a>(tstep: &'a Value, name: &str) -> Result<&'a Map<String, Value>, String> {
+    match tstep
+        .get("controllers")
+        .and_then(|c| c.get(name))
+        .and_then(|c| c.as_object())
+    {
+        Some(v) => Ok(v),
+        None => Err(format!("Timestep has no controller called '{}'", name)),
+    }
+}
+
+/// Gets a number from a controller's output
+pub fn get_f64(obj: &Map<String, Value>, field: &str) -> Result<f64, String> {
+    match obj.get(field).and_then(|v| v.as_f64()) {
+        Some(v) => Ok(v),
+        None => Err(format!("Field '{}' is missing or is not a number", field)),
+    }
+}
+
+/// Gets a boolean from a controller's output
+pub fn get_bool(obj: &Map<String, Value>, field: &str) -> Result<bool, String> {
+    match obj.get(field).and_then(|v| v.as_bool()) {
+        Some(v) => Ok(v),
+        None => Err(format!("Field '{}' is missing or is not a boolean", field)),
+    }
+}
+
+/// Gets a string from a controller's output
+pub fn get_str<'a>(obj: &'a Map<String, Value>, field: &str) -> Result<&'a str, String> {
+    match obj.get(field).and_then(|v| v.as_str()) {
+        Some(v) => Ok(v),
+        None => Err(format!("Field '{}' is missing or is not a string", field)),
+    }
+}
+
+/// Returns the `(perception, space, value)` triplets in the
+/// `current_status` of a person
+pub fn current_status(person: &Map<String, Value>) -> Result<Vec<(String, String, f64)>, String> {
+    let status = match person.get("current_status").and_then(|v| v.as_array()) {
+        Some(v) => v,
+        None => return Err("Person has no 'current_status'".to_string()),
+    };
+
+    let mut ret = Vec::with_capacity(status.len());
+    for per in status {
+        let per = match per.as_object() {
+            Some(v) => v,
+            None => return Err("Elements in 'current_status' must be objects".to_string()),
+        };
+        let perception = get_str(per, "perception")?;
+        let space = get_str(per, "space")?;
+        let value = get_f64(per, "value")?;
+        ret.push((perception.to_string(), space.to_string(), value));
+    }
+    Ok(ret)
+}
+
+/// Returns the `(action, location)` pairs in the
+/// `actions_taken` by a person
+pub fn actions_taken(person: &Map<String, Value>) -> Result<Vec<(String, String)>, String> {
+    let actions = match person.get("actions_taken").and_then(|v| v.as_array()) {
+        Some(v) => v,
+        None => return Err("Person has no 'actions_taken'".to_string()),
+    };
+
+    let mut ret = Vec::with_capacity(actions.len());
+    for v in actions {
+        match v.as_array() {
+            Some(v) if v.len() == 2 => match (v[0].as_str(), v[1].as_str()) {
+                (Some(action), Some(loc)) => ret.push((action.to_string(), loc.to_string())),
+                _ => return Err("Actions taken must be pairs of strings".to_string()),
+            },
+            _ => return Err("Actions taken must be pairs of strings".to_string()),
+        }
+    }
+    Ok(ret)
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+///
+/// It is not cryptographically secure, but it is fast, has good
+/// statistical properties and, more importantly, produces exactly
+/// the same sequence on every platform for a given seed. It can be
+/// serialized, so that interrupted experiments can be resumed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// Creates an independent generator for a sub-stream (e.g. one
+    /// replicate of an experiment), so that results do not depend
+    /// on the order in which the sub-streams are used.
+    pub fn derive(seed: u64, stream: u64) -> Self {
+        let mut rng = Rng::new(seed ^ stream.wrapping_mul(0xD1B5_4A32_D192_ED03));
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number uniformly distributed in [0, 1)
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number uniformly distributed in [min, max)
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.uniform()
+    }
+
+    /// A normally distributed number (Box-Muller)
+    pub fn normal(&mut self, mean: f64, standard_deviation: f64) -> f64 {
+        let u1 = 1. - self.uniform(); // avoid ln(0)
+        let u2 = self.uniform();
+        let z = (-2. * u1.ln()).sqrt() * (2. * std::f64::consts::PI * u2).cos();
+        mean + standard_deviation * z
+    }
+
+    /// An index uniformly distributed in [0, n)
+    pub fn index(&mut self, n: usize) -> usize {
+        ((self.uniform() * n as f64) as usize).min(n - 1)
+    }
+}
+
+/// A probability distribution from which parameters are sampled
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Distribution {
+    Constant { value: f64 },
+    Uniform { min: f64, max: f64 },
+    Normal { mean: f64, standard_deviation: f64 },
+
+    /// A normal distribution, truncated at `min` and `max`
+    TruncatedNormal { mean: f64, standard_deviation: f64, min: f64, max: f64 },
+    Triangular { min: f64, mode: f64, max: f64 },
+}
+
+impl Distribution {
+    /// Checks that the parameters describe a distribution
+    /// that can be sampled
+    pub fn validate(&self) -> Result<(), String> {
+        let (values, ok): (Vec<f64>, bool) = match self {
+            Distribution::Constant { value } => (vec![*value], true),
+            Distribution::Uniform { min, max } => (vec![*min, *max], min < max),
+            Distribution::Normal { mean, standard_deviation } => (vec![*mean, *standard_deviation], *standard_deviation >= 0.),
+            Distribution::TruncatedNormal { mean, standard_deviation, min, max } => (
+                vec![*mean, *standard_deviation, *min, *max],
+                *standard_deviation >= 0. && min < max,
+            ),
+            Distribution::Triangular { min, mode, max } => (vec![*min, *mode, *max], min < max && min <= mode && mode <= max),
+        };
+        if values.iter().any(|v| !v.is_finite()) {
+            return Err(format!("Distribution {:?} has non-finite parameters", self));
+        }
+        if !ok {
+            return Err(format!(
+                "Invalid distribution {:?}: it needs min < max, min <= mode <= max and a non-negative standard deviation",
+                self
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn sample(&self, rng: &mut Rng) -> f64 {
+        match self {
+            Distribution::Constant { value } => *value,
+            Distribution::Uniform { min, max } => rng.range(*min, *max),
+            Distribution::Normal { mean, standard_deviation } => rng.normal(*mean, *standard_deviation),
+            Distribution::TruncatedNormal { mean, standard_deviation, min, max } => {
+                // Rejection sampling... give up after a while and clamp
+                for _ in 0..1000 {
+                    let v = rng.normal(*mean, *standard_deviation);
+                    if v >= *min && v <= *max {
+                        return v;
+                    }
+                }
+                mean.max(*min).min(*max)
+            }
+            Distribution::Triangular { min, mode, max } => {
+                let u = rng.uniform();
+                let f = (mode - min) / (max - min);
+                if u < f {
+                    min + (u * (max - min) * (mode - min)).sqrt()
+                } else {
+                    max - ((1. - u) * (max - min) * (max - mode)).sqrt()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_distributions() {
+        assert!(Distribution::Uniform { min: 0.0, max: 1.0 }.validate().is_ok());
+        assert!(Distribution::Uniform { min: 1.0, max: 0.0 }.validate().is_err());
+        assert!(Distribution::Normal { mean: 0.0, standard_deviation: -1.0 }.validate().is_err());
+        assert!(Distribution::Triangular { min: 0.0, mode: 0.5, max: 1.0 }.validate().is_ok());
+        assert!(Distribution::Triangular { min: 1.0, mode: 1.0, max: 1.0 }.validate().is_err());
+        assert!(Distribution::Triangular { min: 0.0, mode: 2.0, max: 1.0 }.validate().is_err());
+        assert!(Distribution::Constant { value: std::f64::NAN }.validate().is_err());
+    }
+}
@@ -0,0 +1,384 @@
+use std::path::Path;
+use std::time::Instant;
+
+use rayon::prelude::*;
+
+use building_model::building::Building;
+use calendar::date::Date;
+use simulation_state::simulation_state::SimulationState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use weather::Weather;
+
+use crate::cache::ResultCache;
+use crate::case::{create_building, create_person, Case, CaseDefinition};
+use crate::csv_export::{self, CsvDialect};
+use crate::energy_balance::{EnergyBalanceConfig, EnergyBalanceEstimate};
+use crate::epw_file::EpwFile;
+use crate::interpolation::SolarLocation;
+use crate::kpi::{ComfortBand, KpiSummary};
+use crate::metering::{Aggregation, Meters};
+use crate::metadata::{BuildingSummary, Metadata};
+use crate::replay::{ActionLog, StateReplay};
+use crate::results_json::{self, timesteps};
+use crate::state_elements::{space_values, SPACE_DRY_BULB_TEMPERATURE};
+use crate::weather_sources::WeatherSpec;
+
+/// Timesteps per hour used when simulating the thesis cases
+pub const STEPS_PER_HOUR: usize = 60;
+
+/// The period simulated in the thesis cases: two days, starting on July 1st
+pub fn simulation_period() -> (Date, Date) {
+    let start = Date {
+        day: 1,
+        month: 7,
+        hour: 0.0,
+    };
+
+    let mut end = start;
+    end.add_days(2);
+    (start, end)
+}
+
+/// A simulated case
+pub struct Simulation {
+    /// The building, after the simulation
+    pub building: Building,
+
+    /// The JSON representation of the `SimulationResults`
+    pub results: Value,
+
+    /// Where the results came from
+    pub metadata: Metadata,
+
+    /// Timesteps per hour
+    pub n: usize,
+}
+
+impl Simulation {
+    /// Summarizes the simulation
+    pub fn kpi(&self) -> Result<KpiSummary, String> {
+        KpiSummary::from_json(&self.results, &self.building, self.n, ComfortBand::default())
+    }
+}
+
+/// The summary of the building of a case, for the metadata of its results
+fn building_summary(definition: &CaseDefinition, building: &Building, results: &Value) -> Result<BuildingSummary, String> {
+    let mut spaces = Vec::new();
+    if let Some(elements) = timesteps(results)?.first().and_then(|t| t.get("state_elements")) {
+        for (index, _) in space_values(elements, SPACE_DRY_BULB_TEMPERATURE) {
+            spaces.push(building.get_space(index)?.name().clone());
+        }
+    }
+    Ok(BuildingSummary {
+        spaces,
+        window_to_wall_ratio: definition.building.window_to_wall_ratio(),
+    })
+}
+
+/// Simulates a case over the thesis period
+pub fn simulate(definition: &CaseDefinition, weather: &dyn Weather) -> Result<Simulation, String> {
+    simulate_period(definition, weather, simulation_period(), STEPS_PER_HOUR)
+}
+
+/// Simulates a case over a certain period, with `n` timesteps per hour
+pub fn simulate_period(
+    definition: &CaseDefinition,
+    weather: &dyn Weather,
+    (start, end): (Date, Date),
+    n: usize,
+) -> Result<Simulation, String> {
+    let mut state = SimulationState::new();
+    let mut building = Building::new("The Building".to_string());
+    let person = create_person(definition.case, &definition.occupant, &mut state)?;
+    create_building(definition.case, &definition.building, &mut building, &mut state)?;
+
+    let timer = Instant::now();
+    let results = crate::run_with_options(start, end, &person, &mut building, &mut state, weather, n, &definition.options)?;
+    let wall_time = timer.elapsed().as_secs_f64();
+
+    let results = match serde_json::to_value(&results) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Could not serialize results: {}", e)),
+    };
+
+    let summary = building_summary(definition, &building, &results)?;
+    let metadata = Metadata::new(definition, (start, end), n, summary, wall_time);
+
+    Ok(Simulation {
+        building,
+        results,
+        metadata,
+        n,
+    })
+}
+
+/// Simulates the building of a case over the period of a previous run,
+/// operated by replaying its state (see `StateReplay`) with the same
+/// timesteps per hour
+pub fn simulate_replay(definition: &CaseDefinition, weather: &dyn Weather, log: &ActionLog) -> Result<Simulation, String> {
+    let (start, end) = log.period.unwrap_or_else(simulation_period);
+    let n = log.steps_per_hour.unwrap_or(STEPS_PER_HOUR);
+    let mut state = SimulationState::new();
+    let mut building = Building::new("The Building".to_string());
+    create_building(definition.case, &definition.building, &mut building, &mut state)?;
+
+    let timer = Instant::now();
+    let mut replay = StateReplay::new(log, &definition.building);
+    let results = crate::run_state_replay(start, end, &mut replay, &mut building, &mut state, weather, n)?;
+    let wall_time = timer.elapsed().as_secs_f64();
+
+    let results = match serde_json::to_value(&results) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Could not serialize results: {}", e)),
+    };
+    let summary = building_summary(definition, &building, &results)?;
+    let metadata = Metadata::new(definition, (start, end), n, summary, wall_time);
+
+    Ok(Simulation {
+        building,
+        results,
+        metadata,
+        n,
+    })
+}
+
+/// Replays the state recorded in `log_file` (the results of a previous
+/// run) on the building of a case, writing the results and energy
+/// consumption into `output_dir`. Returns the files written.
+pub fn replay_case(
+    definition: &CaseDefinition,
+    weather_file: &str,
+    log_file: &str,
+    output_dir: &str,
+    dialect: &CsvDialect,
+) -> Result<Vec<String>, String> {
+    if let Err(e) = std::fs::create_dir_all(output_dir) {
+        return Err(format!("Could not create directory '{}': {}", output_dir, e));
+    }
+    let output = |suffix: &str| -> String {
+        Path::new(output_dir)
+            .join(format!("{}_replay{}", definition.case.filename(), suffix))
+            .to_string_lossy()
+            .to_string()
+    };
+
+    let log = ActionLog::from_file(log_file)?;
+    let spec = WeatherSpec::from_arg(weather_file)?;
+    let weather = spec.build(&log.period.unwrap_or_else(simulation_period))?;
+    let Simulation {
+        building,
+        results,
+        mut metadata,
+        n,
+    } = simulate_replay(definition, &*weather, &log)?;
+    metadata.weather = Some(weather_file.to_string());
+    metadata.weather_hash = Some(spec.hash()?);
+
+    let mut files = Vec::new();
+    let json_file = output(".json");
+    let content = match serde_json::to_string_pretty(&metadata.wrap(results.clone())?) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Could not serialize results: {}", e)),
+    };
+    if let Err(e) = std::fs::write(&json_file, content) {
+        return Err(format!("Could not write file '{}': {}", json_file, e));
+    }
+    files.push(json_file);
+
+    let meters = Meters::from_json(&results, &building, n)?;
+    for aggregation in [Aggregation::Hourly, Aggregation::Daily, Aggregation::Total].iter() {
+        let energy_file = output(&format!("_energy_{}.csv", aggregation.name()));
+        meters.write_csv(&energy_file, *aggregation, dialect)?;
+        files.push(metadata.write_sidecar(&energy_file)?);
+        files.push(energy_file);
+    }
+    Ok(files)
+}
+
+/// Simulates several cases in parallel, returning their KPIs (or the error
+/// that prevented obtaining them) in the same order
+pub fn simulate_kpis(definitions: &[CaseDefinition], weather: &(dyn Weather + Sync)) -> Vec<Result<KpiSummary, String>> {
+    definitions
+        .par_iter()
+        .map(|definition| simulate(definition, weather)?.kpi())
+        .collect()
+}
+
+/// Reports calculated from the results of a case by `run_case`. They
+/// do not change the results, so cached ones can be reused for them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReportOptions {
+    /// If given, the heat balance of every space and timestep is
+    /// estimated from the results (see `EnergyBalanceEstimate`)
+    #[serde(default)]
+    pub energy_balance: Option<EnergyBalanceConfig>,
+}
+
+/// What is produced by simulating a case
+pub struct CaseOutput {
+    /// Every file written
+    pub files: Vec<String>,
+
+    /// The summary of the run
+    pub kpi: KpiSummary,
+
+    /// Whether the results were taken from the cache instead of simulated
+    pub cached: bool,
+
+    /// The estimated heat balance, if requested in the `ReportOptions`
+    pub energy_balance: Option<EnergyBalanceEstimate>,
+}
+
+/// Builds the building of a case, without simulating it
+fn build_building(definition: &CaseDefinition) -> Result<Building, String> {
+    let mut state = SimulationState::new();
+    let mut building = Building::new("The Building".to_string());
+    create_building(definition.case, &definition.building, &mut building, &mut state)?;
+    Ok(building)
+}
+
+/// Simulates a case with a weather (an EPW file or a JSON weather
+/// specification), writing the results, reports and summaries into `output_dir`.
+///
+/// If a `cache` is given, results of identical inputs are reused
+/// and only the reports and summaries are written again.
+pub fn run_case(
+    definition: &CaseDefinition,
+    weather_file: &str,
+    output_dir: &str,
+    dialect: &CsvDialect,
+    cache: Option<&ResultCache>,
+) -> Result<CaseOutput, String> {
+    if let Err(e) = std::fs::create_dir_all(output_dir) {
+        return Err(format!("Could not create directory '{}': {}", output_dir, e));
+    }
+    let case = definition.case;
+    let output = |suffix: &str| -> String {
+        Path::new(output_dir)
+            .join(format!("{}{}", case.filename(), suffix))
+            .to_string_lossy()
+            .to_string()
+    };
+    let mut files = Vec::new();
+
+    /* SIMULATE */
+    let spec = WeatherSpec::from_arg(weather_file)?;
+    let key = match cache {
+        Some(_) => Some(ResultCache::key(definition, &spec)?),
+        None => None,
+    };
+    let cached = match (cache, &key) {
+        (Some(cache), Some(key)) => cache.load(key)?,
+        _ => None,
+    };
+
+    // Cached results are only usable if their metadata can be read back
+    let cached = cached.and_then(|res| {
+        let metadata = results_json::metadata(&res).and_then(|m| serde_json::from_value::<Metadata>(m.clone()).ok())?;
+        Some((res, metadata))
+    });
+    let (building, res, metadata, n, cached) = match cached {
+        Some((res, mut metadata)) => {
+            // The reports are not part of the key
+            metadata.definition.reports = definition.reports.clone();
+            let n = metadata.steps_per_hour;
+            (build_building(definition)?, res, metadata, n, true)
+        }
+        None => {
+            let weather = spec.build(&simulation_period())?;
+            let Simulation {
+                building,
+                results: res,
+                mut metadata,
+                n,
+            } = simulate(definition, &*weather)?;
+            metadata.weather = Some(weather_file.to_string());
+            metadata.weather_hash = Some(spec.hash()?);
+            let res = metadata.wrap(res)?;
+            if let (Some(cache), Some(key)) = (cache, &key) {
+                cache.store(key, &res)?;
+            }
+            (building, res, metadata, n, false)
+        }
+    };
+
+    /* WRITE RESULTS */
+    let json_file = output(".json");
+    let content = match serde_json::to_string_pretty(&res) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Could not serialize results: {}", e)),
+    };
+    if let Err(e) = std::fs::write(&json_file, content) {
+        return Err(format!("Could not write file '{}': {}", json_file, e));
+    }
+    files.push(json_file);
+
+    let csv_file = output(".csv");
+    match case {
+        Case::Section1_1 => csv_export::write_operation(&csv_file, &building, &res, dialect)?,
+        _ => csv_export::write_comfort(&csv_file, &res, dialect)?,
+    }
+    files.push(csv_file);
+
+    /* ENERGY CONSUMPTION */
+    let meters = Meters::from_json(&res, &building, n)?;
+    for aggregation in [Aggregation::Hourly, Aggregation::Daily, Aggregation::Total].iter() {
+        let energy_file = output(&format!("_energy_{}.csv", aggregation.name()));
+        meters.write_csv(&energy_file, *aggregation, dialect)?;
+        files.push(energy_file);
+    }
+
+    /* KEY PERFORMANCE INDICATORS */
+    let kpi = KpiSummary::from_json(&res, &building, n, ComfortBand::default())?;
+    let kpi_json = output("_kpi.json");
+    let content = match serde_json::to_string_pretty(&json!({ "metadata": metadata, "kpi": kpi })) {
+        Ok(v) => v,
+        Err(e) => return Err(format!("Could not serialize KPI summary: {}", e)),
+    };
+    if let Err(e) = std::fs::write(&kpi_json, content) {
+        return Err(format!("Could not write file '{}': {}", kpi_json, e));
+    }
+    files.push(kpi_json);
+    let kpi_table = output("_kpi.txt");
+    kpi.write_table(&kpi_table)?;
+    files.push(kpi_table);
+
+    /* ENERGY BALANCE */
+    let energy_balance = match &definition.reports.energy_balance {
+        Some(config) => {
+            // The location of the sun is only known for EPW weathers
+            let location = match spec.epw_file() {
+                Some(file) => {
+                    let location = EpwFile::from_file(file)?.location()?;
+                    Some(SolarLocation {
+                        latitude: location.latitude,
+                        longitude: location.longitude,
+                        timezone: location.timezone,
+                    })
+                }
+                None => None,
+            };
+            let balance = EnergyBalanceEstimate::from_json(&res, &definition.building, n, config, location.as_ref())?;
+            let balance_file = output("_energy_balance_estimate.csv");
+            balance.write_csv(&balance_file, dialect)?;
+            files.push(balance_file);
+            Some(balance)
+        }
+        None => None,
+    };
+
+    /* PROVENANCE */
+    // JSON files carry the metadata in a "metadata" field; the others in a sidecar file
+    let others: Vec<String> = files.iter().filter(|f| !f.ends_with(".json")).cloned().collect();
+    for file in others {
+        files.push(metadata.write_sidecar(&file)?);
+    }
+
+    Ok(CaseOutput {
+        files,
+        kpi,
+        cached,
+        energy_balance,
+    })
+}
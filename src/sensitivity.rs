@@ -0,0 +1,461 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use weather::Weather;
+
+use crate::case::{Case, CaseDefinition};
+use crate::csv_export::{write_file, CsvDialect};
+use crate::kpi::KpiSummary;
+use crate::rng::Rng;
+use crate::runner::simulate_kpis;
+use crate::statistics::{mean, standard_deviation};
+
+/// An input whose influence is analysed, varying uniformly between `min` and `max`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Factor {
+    /// The name of the parameter (see `CaseDefinition::set`)
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Factor {
+    /// Maps a value in the unit interval into the range of the factor
+    fn scale(&self, x: f64) -> f64 {
+        self.min + x * (self.max - self.min)
+    }
+}
+
+/// The method used for analysing sensitivity
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SensitivityMethod {
+    /// Morris screening, with `trajectories` one-at-a-time paths
+    /// over a grid with `levels` levels. Requires
+    /// `trajectories * (factors + 1)` simulations.
+    Morris { trajectories: usize, levels: usize },
+
+    /// Variance-based Sobol indices estimated from `samples` base samples
+    /// (Saltelli's design). Requires `samples * (factors + 2)` simulations.
+    Sobol { samples: usize },
+}
+
+/// The configuration of a sensitivity analysis, read from a JSON file such as
+///
+/// ```json
+/// {
+///     "case": "case1",
+///     "weather": "Santiago.epw",
+///     "seed": 1234,
+///     "method": {"type": "morris", "trajectories": 10, "levels": 4},
+///     "factors": [
+///         {"name": "proactivity", "min": 0.0, "max": 1.0},
+///         {"name": "heater_power", "min": 500.0, "max": 2500.0}
+///     ],
+///     "kpis": ["mean_current_comfort", "total_energy"]
+/// }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SensitivityConfig {
+    /// The name of the case (e.g. `case1`)
+    pub case: String,
+
+    /// The weather: an EPW file or a JSON weather specification
+    pub weather: String,
+
+    pub seed: u64,
+    pub method: SensitivityMethod,
+    pub factors: Vec<Factor>,
+
+    /// The KPIs analysed (see `KpiSummary::get`)
+    pub kpis: Vec<String>,
+}
+
+impl SensitivityConfig {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        match serde_json::from_str(&data) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(format!("Could not parse sensitivity configuration '{}': {}", filename, e)),
+        }
+    }
+}
+
+/// The sensitivity of one KPI to one factor
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SensitivityIndex {
+    pub factor: String,
+    pub kpi: String,
+
+    /// Morris: mean of the elementary effects (mu).
+    /// Sobol: first-order index (S1).
+    pub first: f64,
+
+    /// Morris: mean of the absolute elementary effects (mu*).
+    /// Sobol: total-effect index (ST).
+    pub second: f64,
+
+    /// Morris: standard deviation of the elementary effects (sigma).
+    /// Sobol: not used.
+    pub third: f64,
+}
+
+/// The results of a sensitivity analysis
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SensitivityResults {
+    pub config: SensitivityConfig,
+
+    /// The points simulated, in factor units
+    pub design: Vec<Vec<f64>>,
+
+    /// The KPIs obtained in each point (`None` if the simulation failed)
+    pub outputs: Vec<Vec<Option<f64>>>,
+
+    pub indices: Vec<SensitivityIndex>,
+}
+
+/// Builds Morris trajectories in the unit hypercube. Each trajectory
+/// has `k + 1` points, each one differing from the previous in a
+/// single factor. Returns the points and, for each trajectory step,
+/// the factor that changed and the (signed) step taken.
+///
+/// `levels` must be even, so that every step of size
+/// `levels / (2 * (levels - 1))` stays within the unit interval.
+pub fn morris_design(k: usize, trajectories: usize, levels: usize, rng: &mut Rng) -> Result<(Vec<Vec<f64>>, Vec<(usize, f64)>), String> {
+    if levels < 2 || levels % 2 != 0 {
+        return Err(format!("Morris designs need an even number of levels (at least 2)... found {}", levels));
+    }
+    let delta = levels as f64 / (2. * (levels - 1) as f64);
+    let mut points = Vec::with_capacity(trajectories * (k + 1));
+    let mut steps = Vec::with_capacity(trajectories * k);
+
+    for _ in 0..trajectories {
+        let mut x: Vec<f64> = (0..k).map(|_| rng.index(levels) as f64 / (levels - 1) as f64).collect();
+        points.push(x.clone());
+
+        // Random order of factors
+        let mut order: Vec<usize> = (0..k).collect();
+        for i in (1..k).rev() {
+            order.swap(i, rng.index(i + 1));
+        }
+
+        for i in order {
+            let step = if x[i] + delta <= 1. + 1e-9 { delta } else { -delta };
+            x[i] += step;
+            points.push(x.clone());
+            steps.push((i, step));
+        }
+    }
+    Ok((points, steps))
+}
+
+/// Builds Saltelli's design in the unit hypercube: `n` rows of matrix A,
+/// then `n` rows of matrix B, then `n` rows of each matrix AB_i (i.e. A
+/// with the column i taken from B).
+pub fn saltelli_design(k: usize, n: usize, rng: &mut Rng) -> Vec<Vec<f64>> {
+    let a: Vec<Vec<f64>> = (0..n).map(|_| (0..k).map(|_| rng.uniform()).collect()).collect();
+    let b: Vec<Vec<f64>> = (0..n).map(|_| (0..k).map(|_| rng.uniform()).collect()).collect();
+
+    let mut points = Vec::with_capacity(n * (k + 2));
+    points.extend(a.iter().cloned());
+    points.extend(b.iter().cloned());
+    for i in 0..k {
+        for j in 0..n {
+            let mut ab = a[j].clone();
+            ab[i] = b[j][i];
+            points.push(ab);
+        }
+    }
+    points
+}
+
+/// Calculates the Morris statistics (mu, mu*, sigma) of a factor
+fn morris_indices(outputs: &[Option<f64>], steps: &[(usize, f64)], k: usize, factor: usize) -> (f64, f64, f64) {
+    let mut effects = Vec::new();
+    for (s, (i, step)) in steps.iter().enumerate() {
+        if *i != factor {
+            continue;
+        }
+        // Step s of trajectory t goes from point (t*(k+1) + s%k) to the next one
+        let t = s / k;
+        let from = t * (k + 1) + s % k;
+        if let (Some(y0), Some(y1)) = (outputs[from], outputs[from + 1]) {
+            effects.push((y1 - y0) / step);
+        }
+    }
+    let abs: Vec<f64> = effects.iter().map(|e| e.abs()).collect();
+    (mean(&effects), mean(&abs), standard_deviation(&effects))
+}
+
+/// Calculates the first-order (Saltelli, 2010) and total-effect (Jansen)
+/// Sobol indices of a factor
+fn sobol_indices(outputs: &[Option<f64>], n: usize, factor: usize) -> (f64, f64) {
+    let mut fa = Vec::with_capacity(n);
+    let mut fb = Vec::with_capacity(n);
+    let mut fab = Vec::with_capacity(n);
+    for j in 0..n {
+        let ab_index = 2 * n + factor * n + j;
+        if let (Some(a), Some(b), Some(ab)) = (outputs[j], outputs[n + j], outputs[ab_index]) {
+            fa.push(a);
+            fb.push(b);
+            fab.push(ab);
+        }
+    }
+    if fa.len() < 2 {
+        return (std::f64::NAN, std::f64::NAN);
+    }
+
+    let all: Vec<f64> = fa.iter().chain(fb.iter()).cloned().collect();
+    let sd = standard_deviation(&all);
+    let variance = sd * sd;
+    if variance == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let m = fa.len() as f64;
+    let mut first = 0.0;
+    let mut total = 0.0;
+    for j in 0..fa.len() {
+        first += fb[j] * (fab[j] - fa[j]);
+        total += (fa[j] - fab[j]) * (fa[j] - fab[j]);
+    }
+    (first / m / variance, total / (2. * m) / variance)
+}
+
+/// Runs a sensitivity analysis, simulating every point of the design in parallel
+pub fn run_sensitivity(config: &SensitivityConfig, weather: &(dyn Weather + Sync)) -> Result<SensitivityResults, String> {
+    let case = Case::from_name(&config.case)?;
+    let k = config.factors.len();
+    if k == 0 {
+        return Err("At least one factor is needed for a sensitivity analysis".to_string());
+    }
+    let names: Vec<&str> = config.factors.iter().map(|f| f.name.as_str()).collect();
+    CaseDefinition::check_parameters(&names)?;
+    for kpi in config.kpis.iter() {
+        if !KpiSummary::names().contains(&kpi.as_str()) {
+            return Err(format!("Unknown KPI '{}'", kpi));
+        }
+    }
+
+    let mut rng = Rng::new(config.seed);
+    let (unit_design, morris_steps) = match config.method {
+        SensitivityMethod::Morris { trajectories, levels } => morris_design(k, trajectories, levels, &mut rng)?,
+        SensitivityMethod::Sobol { samples } => (saltelli_design(k, samples, &mut rng), Vec::new()),
+    };
+
+    // Build the cases
+    let mut design = Vec::with_capacity(unit_design.len());
+    let mut definitions = Vec::with_capacity(unit_design.len());
+    for x in unit_design.iter() {
+        let values: Vec<f64> = config.factors.iter().zip(x.iter()).map(|(f, x)| f.scale(*x)).collect();
+        let mut definition = CaseDefinition::new(case);
+        for (factor, value) in config.factors.iter().zip(values.iter()) {
+            definition.set(&factor.name, *value)?;
+        }
+        definitions.push(definition);
+        design.push(values);
+    }
+
+    let outputs: Vec<Vec<Option<f64>>> = simulate_kpis(&definitions, weather)
+        .iter()
+        .map(|kpi| {
+            config
+                .kpis
+                .iter()
+                .map(|name| kpi.as_ref().ok().and_then(|k| k.get(name).ok()))
+                .collect()
+        })
+        .collect();
+
+    let mut indices = Vec::with_capacity(k * config.kpis.len());
+    for (kpi_index, kpi) in config.kpis.iter().enumerate() {
+        let y: Vec<Option<f64>> = outputs.iter().map(|o| o[kpi_index]).collect();
+        for (factor_index, factor) in config.factors.iter().enumerate() {
+            let (first, second, third) = match config.method {
+                SensitivityMethod::Morris { .. } => morris_indices(&y, &morris_steps, k, factor_index),
+                SensitivityMethod::Sobol { samples } => {
+                    let (s1, st) = sobol_indices(&y, samples, factor_index);
+                    (s1, st, std::f64::NAN)
+                }
+            };
+            indices.push(SensitivityIndex {
+                factor: factor.name.clone(),
+                kpi: kpi.clone(),
+                first,
+                second,
+                third,
+            });
+        }
+    }
+
+    Ok(SensitivityResults {
+        config: config.clone(),
+        design,
+        outputs,
+        indices,
+    })
+}
+
+impl SensitivityResults {
+    /// The names of the indices, depending on the method
+    fn index_names(&self) -> [&'static str; 3] {
+        match self.config.method {
+            SensitivityMethod::Morris { .. } => ["mu", "mu_star", "sigma"],
+            SensitivityMethod::Sobol { .. } => ["S1", "ST", ""],
+        }
+    }
+
+    /// Writes the indices of every factor and KPI
+    pub fn write_indices_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let names = self.index_names();
+        let mut header = vec!["Factor".to_string(), "KPI".to_string()];
+        header.extend(names.iter().filter(|n| !n.is_empty()).map(|n| n.to_string()));
+
+        let rows: Vec<Vec<String>> = self
+            .indices
+            .iter()
+            .map(|i| {
+                let mut row = vec![i.factor.clone(), i.kpi.clone(), dialect.number(i.first), dialect.number(i.second)];
+                if !names[2].is_empty() {
+                    row.push(dialect.number(i.third));
+                }
+                row
+            })
+            .collect();
+        write_file(filename, &header, &rows, dialect)
+    }
+
+    /// Writes the simulated points and their outputs
+    pub fn write_design_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header: Vec<String> = self.config.factors.iter().map(|f| f.name.clone()).collect();
+        header.extend(self.config.kpis.iter().cloned());
+
+        let rows: Vec<Vec<String>> = self
+            .design
+            .iter()
+            .zip(self.outputs.iter())
+            .map(|(x, y)| {
+                let mut row: Vec<String> = x.iter().map(|v| dialect.number(*v)).collect();
+                row.extend(y.iter().map(|v| v.map(|v| dialect.number(v)).unwrap_or_default()));
+                row
+            })
+            .collect();
+        write_file(filename, &header, &rows, dialect)
+    }
+
+    /// Writes the whole analysis as JSON
+    pub fn write_json(&self, filename: &str) -> Result<(), String> {
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize sensitivity results: {}", e)),
+        };
+        match std::fs::write(filename, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+        }
+    }
+}
+
+impl fmt::Display for SensitivityResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = self.index_names();
+        let failed = self.outputs.iter().filter(|o| o.iter().any(|v| v.is_none())).count();
+        writeln!(f, "{} simulations ({} failed)", self.outputs.len(), failed)?;
+        writeln!(
+            f,
+            "{:<28} {:<24} {:>12} {:>12} {:>12}",
+            "Factor", "KPI", names[0], names[1], names[2]
+        )?;
+        for i in self.indices.iter() {
+            if names[2].is_empty() {
+                writeln!(f, "{:<28} {:<24} {:>12.4} {:>12.4}", i.factor, i.kpi, i.first, i.second)?;
+            } else {
+                writeln!(
+                    f,
+                    "{:<28} {:<24} {:>12.4} {:>12.4} {:>12.4}",
+                    i.factor, i.kpi, i.first, i.second, i.third
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A linear function of two factors, whose indices are known analytically
+    fn linear(x: &[f64]) -> f64 {
+        x[0] + 2. * x[1]
+    }
+
+    #[test]
+    fn morris_design_stays_in_the_unit_hypercube() {
+        let mut rng = Rng::new(1);
+        for levels in [2, 4, 6, 8].iter() {
+            let (points, steps) = morris_design(3, 20, *levels, &mut rng).unwrap();
+            assert_eq!(points.len(), 20 * 4);
+            assert_eq!(steps.len(), 20 * 3);
+            for x in points.iter().flatten() {
+                assert!(*x >= -1e-9 && *x <= 1. + 1e-9, "{} is outside [0, 1] with {} levels", x, levels);
+            }
+        }
+    }
+
+    #[test]
+    fn morris_design_rejects_odd_levels() {
+        let mut rng = Rng::new(1);
+        assert!(morris_design(3, 10, 3, &mut rng).is_err());
+        assert!(morris_design(3, 10, 1, &mut rng).is_err());
+        assert!(morris_design(3, 10, 0, &mut rng).is_err());
+    }
+
+    #[test]
+    fn morris_indices_of_a_linear_function() {
+        let mut rng = Rng::new(7);
+        let (points, steps) = morris_design(2, 10, 4, &mut rng).unwrap();
+        let outputs: Vec<Option<f64>> = points.iter().map(|x| Some(linear(x))).collect();
+
+        let (mu, mu_star, sigma) = morris_indices(&outputs, &steps, 2, 0);
+        assert!((mu - 1.).abs() < 1e-9 && (mu_star - 1.).abs() < 1e-9 && sigma.abs() < 1e-9);
+        let (mu, mu_star, sigma) = morris_indices(&outputs, &steps, 2, 1);
+        assert!((mu - 2.).abs() < 1e-9 && (mu_star - 2.).abs() < 1e-9 && sigma.abs() < 1e-9);
+    }
+
+    #[test]
+    fn saltelli_design_shape() {
+        let mut rng = Rng::new(3);
+        let points = saltelli_design(3, 5, &mut rng);
+        assert_eq!(points.len(), 5 * (3 + 2));
+        // AB_i is A with column i from B
+        for i in 0..3 {
+            for j in 0..5 {
+                let ab = &points[2 * 5 + i * 5 + j];
+                for c in 0..3 {
+                    let expected = if c == i { points[5 + j][c] } else { points[j][c] };
+                    assert_eq!(ab[c], expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_indices_of_a_linear_function() {
+        // Var = 1/12 + 4/12, so S1 = ST = 1/5 and 4/5
+        let n = 20000;
+        let mut rng = Rng::new(11);
+        let points = saltelli_design(2, n, &mut rng);
+        let outputs: Vec<Option<f64>> = points.iter().map(|x| Some(linear(x))).collect();
+
+        let (s1, st) = sobol_indices(&outputs, n, 0);
+        assert!((s1 - 0.2).abs() < 0.03, "S1 = {}", s1);
+        assert!((st - 0.2).abs() < 0.03, "ST = {}", st);
+        let (s1, st) = sobol_indices(&outputs, n, 1);
+        assert!((s1 - 0.8).abs() < 0.03, "S1 = {}", s1);
+        assert!((st - 0.8).abs() < 0.03, "ST = {}", st);
+    }
+}
@@ -0,0 +1,116 @@
+use serde_json::Value;
+use simulation_state::simulation_state::SimulationState;
+use simulation_state::simulation_state_element::SimulationStateElement;
+
+/* Names of the `SimulationStateElement` variants read from the results */
+
+/// Dry bulb temperature of a space, in C
+pub const SPACE_DRY_BULB_TEMPERATURE: &str = "SpaceDryBulbTemperature";
+
+/// Power consumed by the heating/cooling of a space, in W
+pub const SPACE_HEATING_COOLING_POWER: &str = "SpaceHeatingCoolingPowerConsumption";
+
+/// Power consumed by the luminaires of a space, in W
+pub const SPACE_LIGHTING_POWER: &str = "SpaceLightingPowerConsumption";
+
+/// Fraction of a fenestration that is open, between 0 and 1
+pub const FENESTRATION_OPEN_FRACTION: &str = "FenestrationOpenFraction";
+
+/// Temperature of a node of a surface, in C
+pub const SURFACE_NODE_TEMPERATURE: &str = "SurfaceNodeTemperature";
+
+/// Temperature of a node of a fenestration, in C
+pub const FENESTRATION_NODE_TEMPERATURE: &str = "FenestrationNodeTemperature";
+
+/// Outdoor air infiltrating into a space, in m3/s
+pub const SPACE_INFILTRATION_VOLUME: &str = "SpaceInfiltrationVolume";
+
+/// Outdoor air entering a space through its open windows, in m3/s
+pub const SPACE_VENTILATION_VOLUME: &str = "SpaceVentilationVolume";
+
+/// Returns the `(space index, value)` of all the elements of a certain
+/// variant in the JSON representation of the state (i.e. `state_elements`
+/// in a timestep).
+///
+/// Elements are serialized as `{"Variant": [index, value]}`
+pub fn space_values(elements: &Value, variant: &str) -> Vec<(usize, f64)> {
+    let elements = match elements.as_array() {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+
+    elements
+        .iter()
+        .filter_map(|el| el.get(variant))
+        .filter_map(|data| {
+            let data = data.as_array()?;
+            if data.len() < 2 {
+                return None;
+            }
+            let index = data[0].as_u64()? as usize;
+            let value = data[data.len() - 1].as_f64()?;
+            Some((index, value))
+        })
+        .collect()
+}
+
+/// Returns the `(object index, node index, value)` of all the elements
+/// of a certain variant that refer to nodes of an object (e.g. the
+/// temperatures of the nodes of a surface).
+///
+/// Elements are serialized as `{"Variant": [index, node, value]}`
+pub fn node_values(elements: &Value, variant: &str) -> Vec<(usize, usize, f64)> {
+    let elements = match elements.as_array() {
+        Some(v) => v,
+        None => return Vec::new(),
+    };
+
+    elements
+        .iter()
+        .filter_map(|el| el.get(variant))
+        .filter_map(|data| {
+            let data = data.as_array()?;
+            if data.len() != 3 {
+                return None;
+            }
+            Some((data[0].as_u64()? as usize, data[1].as_u64()? as usize, data[2].as_f64()?))
+        })
+        .collect()
+}
+
+/// Sets the value of an element of the state, identified by its
+/// variant (one of the names above) and the index of its object.
+///
+/// Only the elements that occupants operate and the temperatures
+/// of the spaces can be set.
+pub fn set_value(state: &mut SimulationState, variant: &str, index: usize, value: f64) -> Result<(), String> {
+    let element = match variant {
+        SPACE_DRY_BULB_TEMPERATURE => SimulationStateElement::SpaceDryBulbTemperature(index, value),
+        FENESTRATION_OPEN_FRACTION => SimulationStateElement::FenestrationOpenFraction(index, value),
+        SPACE_HEATING_COOLING_POWER => SimulationStateElement::SpaceHeatingCoolingPowerConsumption(index, value),
+        SPACE_LIGHTING_POWER => SimulationStateElement::SpaceLightingPowerConsumption(index, value),
+        _ => return Err(format!("State element '{}' cannot be set", variant)),
+    };
+    let position = state
+        .elements()
+        .iter()
+        .position(|e| std::mem::discriminant(e) == std::mem::discriminant(&element) && element_index(e) == Some(index));
+    match position {
+        Some(i) => {
+            state.update_value(i, element);
+            Ok(())
+        }
+        None => Err(format!("The building has no {} with index {}", variant, index)),
+    }
+}
+
+/// The index of the object an element that can be set refers to
+fn element_index(element: &SimulationStateElement) -> Option<usize> {
+    match element {
+        SimulationStateElement::SpaceDryBulbTemperature(i, _)
+        | SimulationStateElement::FenestrationOpenFraction(i, _)
+        | SimulationStateElement::SpaceHeatingCoolingPowerConsumption(i, _)
+        | SimulationStateElement::SpaceLightingPowerConsumption(i, _) => Some(*i),
+        _ => None,
+    }
+}
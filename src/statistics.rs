@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+/// Descriptive statistics of a sample
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Statistics {
+    /// Number of values in the sample
+    pub count: usize,
+    pub mean: f64,
+    pub standard_deviation: f64,
+    pub min: f64,
+    pub p5: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl Statistics {
+    /// Calculates the statistics of a sample. An empty
+    /// sample returns zeroes everywhere.
+    pub fn new(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Statistics::default();
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mean = mean(values);
+        Statistics {
+            count: values.len(),
+            mean,
+            standard_deviation: standard_deviation(values),
+            min: sorted[0],
+            p5: percentile(&sorted, 5.),
+            p25: percentile(&sorted, 25.),
+            median: percentile(&sorted, 50.),
+            p75: percentile(&sorted, 75.),
+            p95: percentile(&sorted, 95.),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// The arithmetic mean of a sample
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The sample standard deviation
+pub fn standard_deviation(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(values);
+    let s: f64 = values.iter().map(|v| (v - m) * (v - m)).sum();
+    (s / (values.len() - 1) as f64).sqrt()
+}
+
+/// Calculates a percentile (between 0 and 100) of an already
+/// sorted sample, interpolating linearly between values
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.).max(0.).min(1.) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let w = rank - lower as f64;
+    sorted[lower] * (1. - w) + sorted[upper] * w
+}
+
+/// The slope of the least-squares line fitting `y` as a function of `x`
+pub fn linear_trend(x: &[f64], y: &[f64]) -> f64 {
+    assert_eq!(x.len(), y.len());
+    if x.len() < 2 {
+        return 0.0;
+    }
+    let mx = mean(x);
+    let my = mean(y);
+    let mut num = 0.0;
+    let mut den = 0.0;
+    for i in 0..x.len() {
+        num += (x[i] - mx) * (y[i] - my);
+        den += (x[i] - mx) * (x[i] - mx);
+    }
+    if den == 0.0 {
+        0.0
+    } else {
+        num / den
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use weather::Weather;
+
+use crate::case::{Case, CaseDefinition};
+use crate::csv_export::{write_file, CsvDialect};
+use crate::kpi::KpiSummary;
+use crate::rng::Rng;
+use crate::runner::simulate_kpis;
+
+/// How the values of the variables are combined
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SweepDesign {
+    /// Each variable takes each of its `values` while the
+    /// rest keep the values of the case
+    OneAtATime,
+
+    /// Every combination of the `values` of all variables
+    FullFactorial,
+
+    /// `samples` points stratified over the `range` of each variable
+    LatinHypercube { samples: usize, seed: u64 },
+}
+
+/// A variable of the sweep
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SweepVariable {
+    /// The name of the parameter (see `CaseDefinition::set`)
+    pub name: String,
+
+    /// The values taken (one-at-a-time and full-factorial designs)
+    #[serde(default)]
+    pub values: Vec<f64>,
+
+    /// The minimum and maximum values (Latin hypercube design)
+    #[serde(default)]
+    pub range: Option<[f64; 2]>,
+}
+
+/// The configuration of a parametric sweep, read from a JSON file such as
+///
+/// ```json
+/// {
+///     "case": "case1",
+///     "weather": "Santiago.epw",
+///     "design": {"type": "full_factorial"},
+///     "variables": [
+///         {"name": "window_scale", "values": [0.5, 1.0, 1.5]},
+///         {"name": "concrete_thickness", "values": [0.1, 0.18, 0.25]}
+///     ]
+/// }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SweepConfig {
+    /// The name of the case (e.g. `case1`)
+    pub case: String,
+
+    /// The weather: an EPW file or a JSON weather specification
+    pub weather: String,
+
+    pub design: SweepDesign,
+    pub variables: Vec<SweepVariable>,
+}
+
+impl SweepConfig {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = match std::fs::read_to_string(filename) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", filename, e)),
+        };
+        match serde_json::from_str(&data) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(format!("Could not parse sweep configuration '{}': {}", filename, e)),
+        }
+    }
+
+    /// Builds the points of the sweep. Each point is a list of
+    /// `(variable name, value)`; variables not in the list keep
+    /// the values of the case.
+    pub fn points(&self) -> Result<Vec<Vec<(String, f64)>>, String> {
+        if self.variables.is_empty() {
+            return Err("A sweep needs at least one variable".to_string());
+        }
+
+        match &self.design {
+            SweepDesign::OneAtATime | SweepDesign::FullFactorial => {
+                if let Some(v) = self.variables.iter().find(|v| v.values.is_empty()) {
+                    return Err(format!("Variable '{}' has no values", v.name));
+                }
+            }
+            SweepDesign::LatinHypercube { .. } => {
+                if let Some(v) = self.variables.iter().find(|v| v.range.is_none()) {
+                    return Err(format!("Variable '{}' has no range", v.name));
+                }
+            }
+        }
+
+        let points = match &self.design {
+            SweepDesign::OneAtATime => self
+                .variables
+                .iter()
+                .flat_map(|var| var.values.iter().map(move |v| vec![(var.name.clone(), *v)]))
+                .collect(),
+            SweepDesign::FullFactorial => {
+                let mut points: Vec<Vec<(String, f64)>> = vec![Vec::new()];
+                for var in self.variables.iter() {
+                    points = points
+                        .iter()
+                        .flat_map(|p| {
+                            var.values.iter().map(move |v| {
+                                let mut p = p.clone();
+                                p.push((var.name.clone(), *v));
+                                p
+                            })
+                        })
+                        .collect();
+                }
+                points
+            }
+            SweepDesign::LatinHypercube { samples, seed } => {
+                let mut rng = Rng::new(*seed);
+                let n = *samples;
+                let mut points: Vec<Vec<(String, f64)>> = vec![Vec::with_capacity(self.variables.len()); n];
+                for var in self.variables.iter() {
+                    let [min, max] = var.range.unwrap();
+
+                    // One value per stratum, shuffled
+                    let mut strata: Vec<usize> = (0..n).collect();
+                    for i in (1..n).rev() {
+                        strata.swap(i, rng.index(i + 1));
+                    }
+                    for (point, stratum) in points.iter_mut().zip(strata.iter()) {
+                        let x = (*stratum as f64 + rng.uniform()) / n as f64;
+                        point.push((var.name.clone(), min + x * (max - min)));
+                    }
+                }
+                points
+            }
+        };
+        Ok(points)
+    }
+}
+
+/// One point of a sweep, and its outcome
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SweepPoint {
+    pub parameters: Vec<(String, f64)>,
+
+    /// The window-to-wall ratio of the building in this point
+    pub window_to_wall_ratio: f64,
+
+    /// The outcome, or the error that prevented obtaining it
+    pub kpi: Result<KpiSummary, String>,
+}
+
+/// The results of a parametric sweep
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SweepResults {
+    pub config: SweepConfig,
+    pub points: Vec<SweepPoint>,
+}
+
+/// Runs a parametric sweep, simulating every point in parallel
+pub fn run_sweep(config: &SweepConfig, weather: &(dyn Weather + Sync)) -> Result<SweepResults, String> {
+    let case = Case::from_name(&config.case)?;
+    let points = config.points()?;
+
+    let mut definitions = Vec::with_capacity(points.len());
+    for point in points.iter() {
+        let names: Vec<&str> = point.iter().map(|(name, _)| name.as_str()).collect();
+        CaseDefinition::check_parameters(&names)?;
+        let mut definition = CaseDefinition::new(case);
+        for (name, value) in point.iter() {
+            definition.set(name, *value)?;
+        }
+        definitions.push(definition);
+    }
+
+    let kpis = simulate_kpis(&definitions, weather);
+    let points = points
+        .into_iter()
+        .zip(definitions.iter())
+        .zip(kpis.into_iter())
+        .map(|((parameters, definition), kpi)| SweepPoint {
+            parameters,
+            window_to_wall_ratio: definition.building.window_to_wall_ratio(),
+            kpi,
+        })
+        .collect();
+
+    Ok(SweepResults {
+        config: config.clone(),
+        points,
+    })
+}
+
+impl SweepResults {
+    /// Writes a table with one row per point, keyed by the values of
+    /// the variables (empty when a variable keeps the value of the case)
+    pub fn write_csv(&self, filename: &str, dialect: &CsvDialect) -> Result<(), String> {
+        let mut header: Vec<String> = self.config.variables.iter().map(|v| v.name.clone()).collect();
+        header.push("window_to_wall_ratio".to_string());
+        header.extend(KpiSummary::names().iter().map(|s| s.to_string()));
+        header.push("Error".to_string());
+
+        let rows: Vec<Vec<String>> = self
+            .points
+            .iter()
+            .map(|p| {
+                let mut row: Vec<String> = self
+                    .config
+                    .variables
+                    .iter()
+                    .map(|var| {
+                        p.parameters
+                            .iter()
+                            .find(|(name, _)| *name == var.name)
+                            .map(|(_, v)| dialect.number(*v))
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                row.push(dialect.number(p.window_to_wall_ratio));
+                for name in KpiSummary::names() {
+                    row.push(match &p.kpi {
+                        Ok(k) => dialect.number(k.get(name).unwrap_or(std::f64::NAN)),
+                        Err(_) => String::new(),
+                    });
+                }
+                row.push(match &p.kpi {
+                    Ok(_) => String::new(),
+                    Err(e) => dialect.text(e),
+                });
+                row
+            })
+            .collect();
+
+        write_file(filename, &header, &rows, dialect)
+    }
+
+    /// Writes the whole sweep as JSON
+    pub fn write_json(&self, filename: &str) -> Result<(), String> {
+        let content = match serde_json::to_string_pretty(self) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not serialize sweep results: {}", e)),
+        };
+        match std::fs::write(filename, content) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Could not write file '{}': {}", filename, e)),
+        }
+    }
+}
+
+impl fmt::Display for SweepResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for var in self.config.variables.iter() {
+            write!(f, "{:>16} ", var.name)?;
+        }
+        writeln!(f, "{:>8} {:>14} {:>14} {:>14}", "WWR", "Comfort [-]", "Energy [kWh]", "Actions")?;
+        for p in self.points.iter() {
+            for var in self.config.variables.iter() {
+                match p.parameters.iter().find(|(name, _)| *name == var.name) {
+                    Some((_, v)) => write!(f, "{:>16.4} ", v)?,
+                    None => write!(f, "{:>16} ", "-")?,
+                }
+            }
+            write!(f, "{:>8.3} ", p.window_to_wall_ratio)?;
+            match &p.kpi {
+                Ok(k) => writeln!(
+                    f,
+                    "{:>14.4} {:>14.3} {:>14}",
+                    k.current_comfort.mean,
+                    k.heating_energy() + k.lighting_energy(),
+                    k.total_actions()
+                )?,
+                Err(e) => writeln!(f, "FAILED: {}", e)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use weather::current_weather::CurrentWeather;
+use weather::Weather;
+
+use crate::date_utils::hours_between;
+use crate::weather_sources::dew_point;
+
+/// How the intensity of an event evolves between its onset and its end
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventShape {
+    /// Full intensity during the whole event
+    Step,
+
+    /// Grows linearly up to the middle of the event, and then decays
+    Triangular,
+
+    /// Grows linearly during `ramp` hours, and decays during the last `ramp` hours
+    Trapezoidal,
+
+    /// Half a sine wave
+    Sine,
+}
+
+fn default_shape() -> EventShape {
+    EventShape::Sine
+}
+
+fn default_ramp() -> f64 {
+    24.
+}
+
+fn default_solar_factor() -> f64 {
+    1.
+}
+
+/// An extreme event, such as a heatwave or a cold snap,
+/// described in JSON as
+///
+/// ```json
+/// {"onset_month": 7, "onset_day": 1, "duration": 5.0, "peak_delta": 8.0, "shape": "trapezoidal"}
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WeatherEvent {
+    pub onset_month: usize,
+    pub onset_day: usize,
+
+    #[serde(default)]
+    pub onset_hour: f64,
+
+    /// In days
+    pub duration: f64,
+
+    /// Change of the dry bulb temperature at the peak of the
+    /// event, in K (negative for cold snaps)
+    pub peak_delta: f64,
+
+    #[serde(default = "default_shape")]
+    pub shape: EventShape,
+
+    /// Hours of growth and decay of trapezoidal events
+    #[serde(default = "default_ramp")]
+    pub ramp: f64,
+
+    /// Change of the relative humidity at the peak of the event, in percentage points
+    #[serde(default)]
+    pub relative_humidity_delta: f64,
+
+    /// Factor applied to the solar radiation at the peak of the event
+    #[serde(default = "default_solar_factor")]
+    pub solar_factor: f64,
+}
+
+impl WeatherEvent {
+    fn onset(&self) -> Date {
+        Date {
+            month: self.onset_month,
+            day: self.onset_day,
+            hour: self.onset_hour,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.onset_month < 1 || self.onset_month > 12 || self.onset_day < 1 {
+            return Err(format!(
+                "Invalid onset of weather event: month {}, day {}",
+                self.onset_month, self.onset_day
+            ));
+        }
+        if self.duration <= 0.0 {
+            return Err(format!("Weather events need a positive duration (found {})", self.duration));
+        }
+        if self.ramp <= 0.0 {
+            return Err(format!("Weather events need a positive ramp (found {})", self.ramp));
+        }
+        if self.solar_factor < 0.0 {
+            return Err(format!("Solar factors cannot be negative (found {})", self.solar_factor));
+        }
+        Ok(())
+    }
+
+    /// The intensity of the event at a certain date, between 0 (outside
+    /// the event) and 1 (the peak)
+    pub fn intensity(&self, date: &Date) -> f64 {
+        let t = hours_between(&self.onset(), date);
+        let duration = self.duration * 24.;
+        if t < 0.0 || t > duration {
+            return 0.0;
+        }
+        match self.shape {
+            EventShape::Step => 1.0,
+            EventShape::Triangular => 1.0 - (2. * t / duration - 1.).abs(),
+            EventShape::Trapezoidal => (t / self.ramp).min((duration - t) / self.ramp).min(1.0),
+            EventShape::Sine => (std::f64::consts::PI * t / duration).sin(),
+        }
+    }
+}
+
+/// A weather with extreme events overlaid on it
+pub struct EventWeather {
+    weather: Box<dyn Weather + Send + Sync>,
+    events: Vec<WeatherEvent>,
+}
+
+impl EventWeather {
+    pub fn new(weather: Box<dyn Weather + Send + Sync>, events: Vec<WeatherEvent>) -> Result<Self, String> {
+        for event in events.iter() {
+            event.validate()?;
+        }
+        Ok(EventWeather { weather, events })
+    }
+}
+
+impl Weather for EventWeather {
+    fn get_weather_data(&self, date: Date) -> CurrentWeather {
+        let record = self.weather.get_weather_data(date);
+
+        let mut temperature = record.dry_bulb_temperature;
+        let mut relative_humidity = record.relative_humidity;
+        let mut solar = 1.0;
+        for event in self.events.iter() {
+            let s = event.intensity(&date);
+            temperature += s * event.peak_delta;
+            relative_humidity += s * event.relative_humidity_delta;
+            solar *= 1. + s * (event.solar_factor - 1.);
+        }
+        let relative_humidity = relative_humidity.max(0.0).min(100.0);
+
+        CurrentWeather {
+            dry_bulb_temperature: temperature,
+            relative_humidity,
+            dew_point_temperature: dew_point(temperature, relative_humidity),
+            direct_normal_radiation: solar * record.direct_normal_radiation,
+            diffuse_horizontal_radiation: solar * record.diffuse_horizontal_radiation,
+            ..record
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather_sources::ConstantWeather;
+
+    /// A two-day event starting on July 1st at midnight
+    fn event(shape: EventShape) -> WeatherEvent {
+        let mut event: WeatherEvent =
+            serde_json::from_str(r#"{"onset_month": 7, "onset_day": 1, "duration": 2.0, "peak_delta": 8.0}"#).unwrap();
+        event.shape = shape;
+        event
+    }
+
+    /// A date of the event, `t` hours after its onset
+    fn at(t: f64) -> Date {
+        let day = (t / 24.).floor();
+        Date {
+            month: 7,
+            day: 1 + day as usize,
+            hour: t - 24. * day,
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn intensity_outside_and_at_the_peak() {
+        for shape in [EventShape::Step, EventShape::Triangular, EventShape::Trapezoidal, EventShape::Sine].iter() {
+            let e = event(*shape);
+            let before = Date {
+                month: 6,
+                day: 30,
+                hour: 23.0,
+            };
+            assert_eq!(e.intensity(&before), 0.0, "{:?}", shape);
+            assert_eq!(e.intensity(&at(49.0)), 0.0, "{:?}", shape);
+            assert!(close(e.intensity(&at(24.0)), 1.0), "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn trapezoid_ramps() {
+        let mut e = event(EventShape::Trapezoidal);
+        e.ramp = 12.0;
+        assert!(close(e.intensity(&at(0.0)), 0.0));
+        assert!(close(e.intensity(&at(6.0)), 0.5));
+        assert!(close(e.intensity(&at(12.0)), 1.0));
+        assert!(close(e.intensity(&at(30.0)), 1.0));
+        assert!(close(e.intensity(&at(42.0)), 0.5));
+        assert!(close(e.intensity(&at(48.0)), 0.0));
+    }
+
+    #[test]
+    fn relative_humidity_is_clamped() {
+        let base: ConstantWeather = serde_json::from_str(r#"{"dry_bulb_temperature": 20.0, "relative_humidity": 90.0}"#).unwrap();
+        let mut wet = event(EventShape::Step);
+        wet.relative_humidity_delta = 20.0;
+        let mut dry = event(EventShape::Step);
+        dry.relative_humidity_delta = -200.0;
+
+        let weather = EventWeather::new(Box::new(base.clone()), vec![wet]).unwrap();
+        let record = weather.get_weather_data(at(12.0));
+        assert!(close(record.relative_humidity, 100.0));
+        assert!(close(record.dry_bulb_temperature, 28.0));
+
+        let weather = EventWeather::new(Box::new(base), vec![dry]).unwrap();
+        assert!(close(weather.get_weather_data(at(12.0)).relative_humidity, 0.0));
+    }
+
+    #[test]
+    fn invalid_events() {
+        assert!(event(EventShape::Sine).validate().is_ok());
+
+        let mut e = event(EventShape::Sine);
+        e.onset_month = 13;
+        assert!(e.validate().is_err());
+
+        let mut e = event(EventShape::Sine);
+        e.onset_day = 0;
+        assert!(e.validate().is_err());
+
+        let mut e = event(EventShape::Sine);
+        e.duration = 0.0;
+        assert!(e.validate().is_err());
+
+        let mut e = event(EventShape::Trapezoidal);
+        e.ramp = 0.0;
+        assert!(e.validate().is_err());
+
+        let mut e = event(EventShape::Sine);
+        e.solar_factor = -0.5;
+        assert!(e.validate().is_err());
+
+        let base: ConstantWeather = serde_json::from_str(r#"{"dry_bulb_temperature": 20.0}"#).unwrap();
+        assert!(EventWeather::new(Box::new(base), vec![e]).is_err());
+    }
+}
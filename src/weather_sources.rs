@@ -0,0 +1,567 @@
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+use weather::current_weather::CurrentWeather;
+use weather::epw_weather::EPWWeather;
+use weather::Weather;
+
+use crate::date_utils::{hour_of_year, hours_between};
+use crate::epw_file::EpwFile;
+use crate::hashing::ContentHash;
+use crate::interpolation::{Interpolation, InterpolatedWeather, SolarLocation};
+use crate::morphing::{epw_monthly_means, MonthlyDeltas, MorphedWeather};
+use crate::weather_events::{EventWeather, WeatherEvent};
+use crate::weather_validation::{validate_epw, WeatherReport};
+
+/// Builds a weather record. Fields that cannot be derived
+/// from the given values are left at their defaults.
+pub fn weather_record(
+    date: Date,
+    dry_bulb_temperature: f64,
+    relative_humidity: f64,
+    direct_normal_radiation: f64,
+    diffuse_horizontal_radiation: f64,
+    wind_speed: f64,
+) -> CurrentWeather {
+    CurrentWeather {
+        date,
+        dry_bulb_temperature,
+        dew_point_temperature: dew_point(dry_bulb_temperature, relative_humidity),
+        relative_humidity,
+        direct_normal_radiation,
+        diffuse_horizontal_radiation,
+        wind_speed,
+        ..CurrentWeather::default()
+    }
+}
+
+/// Dew point temperature (in C) from the dry bulb temperature (in C)
+/// and the relative humidity (in %), using the Magnus formula
+pub fn dew_point(dry_bulb_temperature: f64, relative_humidity: f64) -> f64 {
+    const B: f64 = 17.62;
+    const C: f64 = 243.12;
+    let rh = relative_humidity.max(1.).min(100.) / 100.;
+    let gamma = rh.ln() + B * dry_bulb_temperature / (C + dry_bulb_temperature);
+    C * gamma / (B - gamma)
+}
+
+/// A weather that never changes, for controlled experiments
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConstantWeather {
+    /// In C
+    pub dry_bulb_temperature: f64,
+
+    /// In %
+    #[serde(default = "default_relative_humidity")]
+    pub relative_humidity: f64,
+
+    /// In W/m2
+    #[serde(default)]
+    pub direct_normal_radiation: f64,
+
+    /// In W/m2
+    #[serde(default)]
+    pub diffuse_horizontal_radiation: f64,
+
+    /// In m/s
+    #[serde(default)]
+    pub wind_speed: f64,
+}
+
+fn default_relative_humidity() -> f64 {
+    50.
+}
+
+impl Weather for ConstantWeather {
+    fn get_weather_data(&self, date: Date) -> CurrentWeather {
+        weather_record(
+            date,
+            self.dry_bulb_temperature,
+            self.relative_humidity,
+            self.direct_normal_radiation,
+            self.diffuse_horizontal_radiation,
+            self.wind_speed,
+        )
+    }
+}
+
+/// Fraction of the daily range below the maximum temperature at the
+/// end of each hour of a design day (ASHRAE Fundamentals)
+const DESIGN_DAY_FRACTIONS: [f64; 24] = [
+    0.87, 0.92, 0.96, 0.99, 1.00, 0.98, 0.93, 0.84, 0.71, 0.56, 0.39, 0.23, 0.11, 0.03, 0.00, 0.03, 0.10, 0.21, 0.34,
+    0.47, 0.58, 0.68, 0.76, 0.82,
+];
+
+/// A design day, repeated every day of the year
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DesignDayWeather {
+    /// Maximum dry bulb temperature, in C
+    pub max_dry_bulb_temperature: f64,
+
+    /// Difference between maximum and minimum dry bulb temperatures, in K
+    pub daily_range: f64,
+
+    /// In %
+    #[serde(default = "default_relative_humidity")]
+    pub relative_humidity: f64,
+
+    /// Direct normal radiation at solar noon, in W/m2
+    #[serde(default)]
+    pub peak_direct_normal_radiation: f64,
+
+    /// Diffuse horizontal radiation at solar noon, in W/m2
+    #[serde(default)]
+    pub peak_diffuse_horizontal_radiation: f64,
+
+    /// Hour of sunrise (radiation follows a half-sine between sunrise and sunset)
+    #[serde(default = "default_sunrise")]
+    pub sunrise: f64,
+
+    /// Hour of sunset
+    #[serde(default = "default_sunset")]
+    pub sunset: f64,
+
+    /// In m/s
+    #[serde(default)]
+    pub wind_speed: f64,
+}
+
+fn default_sunrise() -> f64 {
+    6.
+}
+
+fn default_sunset() -> f64 {
+    18.
+}
+
+/// A half-sine between `start` and `end` hours, with a peak of 1
+fn daylight_shape(hour: f64, start: f64, end: f64) -> f64 {
+    if hour <= start || hour >= end {
+        0.0
+    } else {
+        (std::f64::consts::PI * (hour - start) / (end - start)).sin()
+    }
+}
+
+impl Weather for DesignDayWeather {
+    fn get_weather_data(&self, date: Date) -> CurrentWeather {
+        // Fractions are given at the end of each hour
+        let h = date.hour.rem_euclid(24.);
+        let i = h.floor() as usize;
+        let before = DESIGN_DAY_FRACTIONS[(i + 23) % 24];
+        let after = DESIGN_DAY_FRACTIONS[i % 24];
+        let fraction = before + (after - before) * (h - h.floor());
+
+        let sun = daylight_shape(h, self.sunrise, self.sunset);
+        weather_record(
+            date,
+            self.max_dry_bulb_temperature - fraction * self.daily_range,
+            self.relative_humidity,
+            sun * self.peak_direct_normal_radiation,
+            sun * self.peak_diffuse_horizontal_radiation,
+            self.wind_speed,
+        )
+    }
+}
+
+/// A weather whose temperature follows a sine wave, mostly meant for tests
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SineWeather {
+    /// Mean dry bulb temperature, in C
+    pub mean_temperature: f64,
+
+    /// Amplitude of the dry bulb temperature, in K
+    pub amplitude: f64,
+
+    /// Period of the wave, in hours
+    #[serde(default = "default_period")]
+    pub period: f64,
+
+    /// Hour of the day at which the temperature peaks
+    #[serde(default = "default_peak_hour")]
+    pub peak_hour: f64,
+
+    /// In %
+    #[serde(default = "default_relative_humidity")]
+    pub relative_humidity: f64,
+
+    /// Direct normal radiation at noon, in W/m2 (half-sine from 6:00 to 18:00)
+    #[serde(default)]
+    pub peak_direct_normal_radiation: f64,
+
+    /// Diffuse horizontal radiation at noon, in W/m2
+    #[serde(default)]
+    pub peak_diffuse_horizontal_radiation: f64,
+}
+
+fn default_period() -> f64 {
+    24.
+}
+
+fn default_peak_hour() -> f64 {
+    15.
+}
+
+impl SineWeather {
+    pub fn new(mean_temperature: f64, amplitude: f64) -> Self {
+        SineWeather {
+            mean_temperature,
+            amplitude,
+            period: default_period(),
+            peak_hour: default_peak_hour(),
+            relative_humidity: default_relative_humidity(),
+            peak_direct_normal_radiation: 0.0,
+            peak_diffuse_horizontal_radiation: 0.0,
+        }
+    }
+}
+
+impl Weather for SineWeather {
+    fn get_weather_data(&self, date: Date) -> CurrentWeather {
+        let t = hour_of_year(&date) - self.peak_hour;
+        let temperature = self.mean_temperature + self.amplitude * (2. * std::f64::consts::PI * t / self.period).cos();
+        let sun = daylight_shape(date.hour, 6., 18.);
+        weather_record(
+            date,
+            temperature,
+            self.relative_humidity,
+            sun * self.peak_direct_normal_radiation,
+            sun * self.peak_diffuse_horizontal_radiation,
+            0.0,
+        )
+    }
+}
+
+/// The columns (starting from 0) of a CSV weather file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsvWeatherColumns {
+    pub month: usize,
+    pub day: usize,
+
+    /// Hour of the day, from 0 to 24
+    pub hour: usize,
+
+    /// In C
+    pub dry_bulb_temperature: usize,
+
+    /// In %
+    #[serde(default)]
+    pub relative_humidity: Option<usize>,
+
+    /// In W/m2
+    #[serde(default)]
+    pub direct_normal_radiation: Option<usize>,
+
+    /// In W/m2
+    #[serde(default)]
+    pub diffuse_horizontal_radiation: Option<usize>,
+
+    /// In m/s
+    #[serde(default)]
+    pub wind_speed: Option<usize>,
+}
+
+/// How to read a CSV weather file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsvWeatherConfig {
+    pub file: String,
+
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+
+    /// Number of lines to skip at the beginning of the file
+    #[serde(default = "default_header_rows")]
+    pub header_rows: usize,
+
+    pub columns: CsvWeatherColumns,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_header_rows() -> usize {
+    1
+}
+
+/// A weather read from a time series in a CSV file. Values between
+/// records are interpolated linearly (wrapping around the end of the file).
+#[derive(Clone, Debug)]
+pub struct CsvWeather {
+    /// The records, sorted by date
+    records: Vec<CurrentWeather>,
+}
+
+impl CsvWeather {
+    pub fn from_file(config: &CsvWeatherConfig) -> Result<Self, String> {
+        let content = match std::fs::read_to_string(&config.file) {
+            Ok(v) => v,
+            Err(e) => return Err(format!("Could not read file '{}': {}", config.file, e)),
+        };
+        let cols = &config.columns;
+
+        let mut records = Vec::new();
+        for (line_index, line) in content.lines().enumerate().skip(config.header_rows) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(config.delimiter).map(|f| f.trim()).collect();
+            let get = |col: usize| -> Result<f64, String> {
+                match fields.get(col).map(|f| f.parse::<f64>()) {
+                    Some(Ok(v)) => Ok(v),
+                    _ => Err(format!(
+                        "Could not read column {} in line {} of '{}'",
+                        col,
+                        line_index + 1,
+                        config.file
+                    )),
+                }
+            };
+            let get_optional = |col: Option<usize>, default: f64| -> Result<f64, String> {
+                match col {
+                    Some(c) => get(c),
+                    None => Ok(default),
+                }
+            };
+
+            let date = Date {
+                month: get(cols.month)? as usize,
+                day: get(cols.day)? as usize,
+                hour: get(cols.hour)?,
+            };
+            if date.month < 1 || date.month > 12 || date.day < 1 {
+                return Err(format!("Invalid date in line {} of '{}'", line_index + 1, config.file));
+            }
+
+            records.push(weather_record(
+                date,
+                get(cols.dry_bulb_temperature)?,
+                get_optional(cols.relative_humidity, default_relative_humidity())?,
+                get_optional(cols.direct_normal_radiation, 0.0)?,
+                get_optional(cols.diffuse_horizontal_radiation, 0.0)?,
+                get_optional(cols.wind_speed, 0.0)?,
+            ));
+        }
+
+        if records.is_empty() {
+            return Err(format!("No weather records found in '{}'", config.file));
+        }
+        records.sort_by(|a, b| {
+            hour_of_year(&a.date)
+                .partial_cmp(&hour_of_year(&b.date))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(CsvWeather { records })
+    }
+}
+
+impl Weather for CsvWeather {
+    fn get_weather_data(&self, date: Date) -> CurrentWeather {
+        let t = hour_of_year(&date);
+        let n = self.records.len();
+
+        // The first record after the date (wrapping around)
+        let next = self
+            .records
+            .iter()
+            .position(|r| hour_of_year(&r.date) > t)
+            .unwrap_or(0);
+        let prev = (next + n - 1) % n;
+        let a = &self.records[prev];
+        let b = &self.records[next];
+
+        let span = hours_between(&a.date, &b.date).rem_euclid(8760.);
+        let w = if span > 0.0 {
+            hours_between(&a.date, &date).rem_euclid(8760.) / span
+        } else {
+            0.0
+        };
+        let lerp = |x: f64, y: f64| x + (y - x) * w;
+
+        weather_record(
+            date,
+            lerp(a.dry_bulb_temperature, b.dry_bulb_temperature),
+            lerp(a.relative_humidity, b.relative_humidity),
+            lerp(a.direct_normal_radiation, b.direct_normal_radiation),
+            lerp(a.diffuse_horizontal_radiation, b.diffuse_horizontal_radiation),
+            lerp(a.wind_speed, b.wind_speed),
+        )
+    }
+}
+
+/// A description of where the weather comes from. It can be read
+/// from a JSON file such as
+///
+/// ```json
+/// {"type": "design_day", "max_dry_bulb_temperature": 32.0, "daily_range": 12.0}
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WeatherSpec {
+    Epw { file: String },
+    Constant(ConstantWeather),
+    DesignDay(DesignDayWeather),
+    Sine(SineWeather),
+    Csv(CsvWeatherConfig),
+
+    /// Another weather, morphed with the monthly factors in the `deltas` file
+    Morphed { weather: Box<WeatherSpec>, deltas: String },
+
+    /// Another weather, with heatwaves or cold snaps overlaid on it
+    Events { weather: Box<WeatherSpec>, events: Vec<WeatherEvent> },
+
+    /// Another weather, interpolated between its hourly records
+    Interpolated {
+        weather: Box<WeatherSpec>,
+        interpolation: Interpolation,
+    },
+}
+
+impl WeatherSpec {
+    /// Interprets a command line argument: EPW files are used directly,
+    /// and JSON files are expected to contain a `WeatherSpec`
+    pub fn from_arg(arg: &str) -> Result<Self, String> {
+        let lower = arg.to_lowercase();
+        if lower.ends_with(".epw") {
+            Ok(WeatherSpec::Epw { file: arg.to_string() })
+        } else if lower.ends_with(".json") {
+            let data = match std::fs::read_to_string(arg) {
+                Ok(v) => v,
+                Err(e) => return Err(format!("Could not read file '{}': {}", arg, e)),
+            };
+            match serde_json::from_str(&data) {
+                Ok(v) => Ok(v),
+                Err(e) => Err(format!("Could not parse weather specification '{}': {}", arg, e)),
+            }
+        } else {
+            Err(format!(
+                "Weather '{}' should be an EPW file or a JSON weather specification",
+                arg
+            ))
+        }
+    }
+
+    /// The EPW file the weather is based on, if any
+    pub fn epw_file(&self) -> Option<&str> {
+        match self {
+            WeatherSpec::Epw { file } => Some(file),
+            WeatherSpec::Morphed { weather, .. }
+            | WeatherSpec::Events { weather, .. }
+            | WeatherSpec::Interpolated { weather, .. } => weather.epw_file(),
+            _ => None,
+        }
+    }
+
+    /// Every file read when building the weather
+    pub fn files(&self) -> Vec<&str> {
+        match self {
+            WeatherSpec::Epw { file } => vec![file],
+            WeatherSpec::Csv(config) => vec![&config.file],
+            WeatherSpec::Morphed { weather, deltas } => {
+                let mut files = weather.files();
+                files.push(deltas);
+                files
+            }
+            WeatherSpec::Events { weather, .. } | WeatherSpec::Interpolated { weather, .. } => weather.files(),
+            WeatherSpec::Constant(_) | WeatherSpec::DesignDay(_) | WeatherSpec::Sine(_) => Vec::new(),
+        }
+    }
+
+    /// A hash of the specification and of the contents of every file it reads
+    pub fn hash(&self) -> Result<String, String> {
+        let mut hash = ContentHash::new();
+        match serde_json::to_string(self) {
+            Ok(v) => hash.update_str(&v),
+            Err(e) => return Err(format!("Could not serialize weather specification: {}", e)),
+        }
+        for file in self.files() {
+            hash.update_file(file)?;
+        }
+        Ok(hash.hex())
+    }
+
+    /// Completes a location with the one in the EPW file, if any
+    fn solar_location(
+        &self,
+        latitude: Option<f64>,
+        longitude: Option<f64>,
+        timezone: Option<f64>,
+    ) -> Result<SolarLocation, String> {
+        if let (Some(latitude), Some(longitude), Some(timezone)) = (latitude, longitude, timezone) {
+            return Ok(SolarLocation {
+                latitude,
+                longitude,
+                timezone,
+            });
+        }
+        let file = match self.epw_file() {
+            Some(f) => f,
+            None => return Err("Solar interpolation needs a latitude, longitude and timezone".to_string()),
+        };
+        let location = EpwFile::from_file(file)?.location()?;
+        Ok(SolarLocation {
+            latitude: latitude.unwrap_or(location.latitude),
+            longitude: longitude.unwrap_or(location.longitude),
+            timezone: timezone.unwrap_or(location.timezone),
+        })
+    }
+
+    /// Checks the EPW file the weather is based on (if any) for the
+    /// `period` simulated, returning its report. Errors in the file are
+    /// returned as an error.
+    pub fn check(&self, period: &(Date, Date)) -> Result<Option<WeatherReport>, String> {
+        let file = match self.epw_file() {
+            Some(f) => f,
+            None => return Ok(None),
+        };
+        let report = validate_epw(file, &EpwFile::from_file(file)?, Some(period));
+        if !report.is_valid() {
+            return Err(format!("Invalid weather file '{}': {}", file, report.errors()));
+        }
+        Ok(Some(report))
+    }
+
+    /// Builds the weather that will be simulated over a certain `period`,
+    /// after checking it (see `check`), so that broken files do not become
+    /// a panic or a bad simulation
+    pub fn build(&self, period: &(Date, Date)) -> Result<Box<dyn Weather + Send + Sync>, String> {
+        self.check(period)?;
+        self.build_unchecked()
+    }
+
+    fn build_unchecked(&self) -> Result<Box<dyn Weather + Send + Sync>, String> {
+        let weather: Box<dyn Weather + Send + Sync> = match self {
+            WeatherSpec::Epw { file } => Box::new(EPWWeather::from_file(file.clone())),
+            WeatherSpec::Constant(w) => Box::new(w.clone()),
+            WeatherSpec::DesignDay(w) => Box::new(w.clone()),
+            WeatherSpec::Sine(w) => Box::new(w.clone()),
+            WeatherSpec::Csv(config) => Box::new(CsvWeather::from_file(config)?),
+            WeatherSpec::Morphed { weather, deltas } => {
+                let deltas = MonthlyDeltas::from_file(deltas)?;
+                let original = weather.build_unchecked()?;
+                // Use the same statistics as `morph_epw` when there are records
+                let monthly_mean = match weather.as_ref() {
+                    WeatherSpec::Epw { file } => epw_monthly_means(&EpwFile::from_file(file)?)?,
+                    _ => MorphedWeather::sampled_means(original.as_ref()),
+                };
+                Box::new(MorphedWeather::new(original, deltas, monthly_mean))
+            }
+            WeatherSpec::Events { weather, events } => {
+                let original = weather.build_unchecked()?;
+                Box::new(EventWeather::new(original, events.clone())?)
+            }
+            WeatherSpec::Interpolated { weather, interpolation } => {
+                let location = match interpolation {
+                    Interpolation::Solar {
+                        latitude,
+                        longitude,
+                        timezone,
+                    } => Some(weather.solar_location(*latitude, *longitude, *timezone)?),
+                    _ => None,
+                };
+                let original = weather.build_unchecked()?;
+                Box::new(InterpolatedWeather::new(original, interpolation.clone(), location)?)
+            }
+        };
+        Ok(weather)
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use calendar::date::Date;
+use serde::{Deserialize, Serialize};
+
+use crate::date_utils::{days_in_month, hour_of_year};
+use crate::epw_file::{self, EpwFile, EpwLocation};
+use crate::statistics::Statistics;
+
+/// The names of the header lines of an EPW file, in order
+const HEADER_NAMES: [&str; epw_file::HEADER_LINES] = [
+    "LOCATION",
+    "DESIGN CONDITIONS",
+    "TYPICAL/EXTREME PERIODS",
+    "GROUND TEMPERATURES",
+    "HOLIDAYS/DAYLIGHT SAVINGS",
+    "COMMENTS 1",
+    "COMMENTS 2",
+    "DATA PERIODS",
+];
+
+/// The valid range of some fields, as in the EnergyPlus Auxiliary
+/// Programs documentation: `(field, name, min, max, missing value)`
+const FIELD_RANGES: [(usize, &str, f64, f64, f64); 8] = [
+    (epw_file::DRY_BULB_TEMPERATURE, "dry bulb temperature", -70., 70., 99.9),
+    (epw_file::DEW_POINT_TEMPERATURE, "dew point temperature", -70., 70., 99.9),
+    (epw_file::RELATIVE_HUMIDITY, "relative humidity", 0., 110., 999.),
+    (epw_file::ATMOSPHERIC_PRESSURE, "atmospheric pressure", 31000., 120000., 999999.),
+    (epw_file::GLOBAL_HORIZONTAL_RADIATION, "global horizontal radiation", 0., 9998., 9999.),
+    (epw_file::DIRECT_NORMAL_RADIATION, "direct normal radiation", 0., 9998., 9999.),
+    (epw_file::DIFFUSE_HORIZONTAL_RADIATION, "diffuse horizontal radiation", 0., 9998., 9999.),
+    (epw_file::WIND_SPEED, "wind speed", 0., 40., 999.),
+];
+
+/// How bad an issue is
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Severity {
+    /// The simulation may still work, but the results should be checked
+    Warning,
+
+    /// The file should not be used
+    Error,
+}
+
+/// A problem found in a weather file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Monthly statistics of a weather file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonthlyWeather {
+    pub month: usize,
+
+    /// Dry bulb temperature, in C
+    pub temperature: Statistics,
+
+    /// In kWh/m2
+    pub global_horizontal_radiation: f64,
+
+    /// In kWh/m2
+    pub direct_normal_radiation: f64,
+
+    /// In kWh/m2
+    pub diffuse_horizontal_radiation: f64,
+}
+
+/// The outcome of checking a weather file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WeatherReport {
+    pub filename: String,
+    pub city: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub timezone: Option<f64>,
+
+    /// Number of data records
+    pub records: usize,
+
+    pub issues: Vec<Issue>,
+    pub monthly: Vec<MonthlyWeather>,
+}
+
+impl WeatherReport {
+    /// Whether the file can be used for simulating
+    pub fn is_valid(&self) -> bool {
+        self.issues.iter().all(|i| i.severity != Severity::Error)
+    }
+
+    /// Joins the errors in a single message
+    pub fn errors(&self) -> String {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| i.message.clone())
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+}
+
+/// Lists some of the elements of a (possibly long) list
+fn summarize(items: &[String]) -> String {
+    const SHOWN: usize = 5;
+    if items.len() <= SHOWN {
+        items.join(", ")
+    } else {
+        format!("{}, ... ({} more)", items[..SHOWN].join(", "), items.len() - SHOWN)
+    }
+}
+
+fn check_location(location: &EpwLocation, issues: &mut Vec<Issue>) {
+    let mut error = |message: String| {
+        issues.push(Issue {
+            severity: Severity::Error,
+            message,
+        })
+    };
+    if location.latitude.abs() > 90. {
+        error(format!("Latitude {} is out of range", location.latitude));
+    }
+    if location.longitude.abs() > 180. {
+        error(format!("Longitude {} is out of range", location.longitude));
+    }
+    if location.timezone < -12. || location.timezone > 14. {
+        error(format!("Timezone {} is out of range", location.timezone));
+    }
+
+    // Each hour of timezone is roughly 15 degrees of longitude
+    if (location.longitude - 15. * location.timezone).abs() > 30. {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            message: format!(
+                "Timezone {} does not match longitude {}; check their signs",
+                location.timezone, location.longitude
+            ),
+        });
+    }
+}
+
+/// Whether the hour ending at `hour` (1 to 24, as in EPW records) of a day
+/// overlaps a period, which may go past the end of the year
+fn in_period(month: usize, day: usize, hour: usize, (start, end): &(Date, Date)) -> bool {
+    let t = hour_of_year(&Date {
+        month,
+        day,
+        hour: (hour - 1) as f64,
+    });
+    let (start, end) = (hour_of_year(start), hour_of_year(end));
+    if start < end {
+        t < end && t + 1. > start
+    } else {
+        t < end || t + 1. > start
+    }
+}
+
+/// Checks the header, the completeness and the values of an EPW file.
+///
+/// If a `period` is given, only hours missing within it are errors, so
+/// that files covering part of a year (e.g. measured weather) can be used.
+pub fn validate_epw(filename: &str, file: &EpwFile, period: Option<&(Date, Date)>) -> WeatherReport {
+    let mut issues = Vec::new();
+
+    /* HEADER */
+    for (line, name) in file.header.iter().zip(HEADER_NAMES.iter()) {
+        if !line.starts_with(name) {
+            issues.push(Issue {
+                severity: Severity::Warning,
+                message: format!("Expected a '{}' header line, found '{}'", name, line.split(',').next().unwrap_or("")),
+            });
+        }
+    }
+    let location = match file.location() {
+        Ok(l) => {
+            check_location(&l, &mut issues);
+            Some(l)
+        }
+        Err(e) => {
+            issues.push(Issue {
+                severity: Severity::Error,
+                message: e,
+            });
+            None
+        }
+    };
+    let leap_year_observed = file.header[4]
+        .split(',')
+        .nth(1)
+        .map(|f| f.trim().eq_ignore_ascii_case("yes"))
+        .unwrap_or(false);
+
+    /* RECORDS */
+    let mut seen: BTreeMap<(usize, usize, usize), usize> = BTreeMap::new();
+    let mut malformed = Vec::new();
+    let mut out_of_range: Vec<Vec<String>> = vec![Vec::new(); FIELD_RANGES.len()];
+    let mut missing_values: Vec<usize> = vec![0; FIELD_RANGES.len()];
+    let mut monthly_values: Vec<[Vec<f64>; 4]> = (0..12).map(|_| Default::default()).collect();
+
+    for i in 0..file.records.len() {
+        let date = (
+            file.value(i, epw_file::MONTH),
+            file.value(i, epw_file::DAY),
+            file.value(i, epw_file::HOUR),
+        );
+        let (month, day, hour) = match date {
+            (Ok(m), Ok(d), Ok(h)) if m >= 1. && m <= 12. && d >= 1. && h >= 1. && h <= 24. => {
+                (m as usize, d as usize, h as usize)
+            }
+            _ => {
+                malformed.push(format!("line {}", i + 1 + epw_file::HEADER_LINES));
+                continue;
+            }
+        };
+        if file.records[i].len() < epw_file::FIELDS {
+            malformed.push(format!("line {}", i + 1 + epw_file::HEADER_LINES));
+        }
+        *seen.entry((month, day, hour)).or_insert(0) += 1;
+
+        for (j, (field, _, min, max, missing)) in FIELD_RANGES.iter().enumerate() {
+            match file.value(i, *field) {
+                Ok(v) if (v - missing).abs() < 1e-9 => missing_values[j] += 1,
+                Ok(v) if v >= *min && v <= *max => {}
+                Ok(v) => out_of_range[j].push(format!("{}/{} {}h ({})", day, month, hour, v)),
+                Err(_) => out_of_range[j].push(format!("{}/{} {}h (not a number)", day, month, hour)),
+            }
+        }
+
+        let values = &mut monthly_values[month - 1];
+        for (k, field) in [
+            epw_file::DRY_BULB_TEMPERATURE,
+            epw_file::GLOBAL_HORIZONTAL_RADIATION,
+            epw_file::DIRECT_NORMAL_RADIATION,
+            epw_file::DIFFUSE_HORIZONTAL_RADIATION,
+        ]
+        .iter()
+        .enumerate()
+        {
+            if let Ok(v) = file.value(i, *field) {
+                if !epw_file::is_missing(*field, v) {
+                    values[k].push(v);
+                }
+            }
+        }
+    }
+
+    if !malformed.is_empty() {
+        issues.push(Issue {
+            severity: Severity::Error,
+            message: format!("{} malformed records: {}", malformed.len(), summarize(&malformed)),
+        });
+    }
+
+    // Missing and repeated hours
+    let mut missing_hours = Vec::new();
+    let mut missing_outside = Vec::new();
+    for month in 1..=12 {
+        for day in 1..=days_in_month(month) {
+            for hour in 1..=24 {
+                if !seen.contains_key(&(month, day, hour)) {
+                    let missing = format!("{}/{} {}h", day, month, hour);
+                    match period {
+                        Some(p) if !in_period(month, day, hour, p) => missing_outside.push(missing),
+                        _ => missing_hours.push(missing),
+                    }
+                }
+            }
+        }
+    }
+    if !missing_hours.is_empty() {
+        issues.push(Issue {
+            severity: Severity::Error,
+            message: format!("{} missing hours: {}", missing_hours.len(), summarize(&missing_hours)),
+        });
+    }
+    if !missing_outside.is_empty() {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            message: format!(
+                "{} missing hours outside the simulated period: {}",
+                missing_outside.len(),
+                summarize(&missing_outside)
+            ),
+        });
+    }
+    let repeated: Vec<String> = seen
+        .iter()
+        .filter(|(_, count)| **count > 1)
+        .map(|((m, d, h), _)| format!("{}/{} {}h", d, m, h))
+        .collect();
+    if !repeated.is_empty() {
+        issues.push(Issue {
+            severity: Severity::Error,
+            message: format!("{} repeated hours: {}", repeated.len(), summarize(&repeated)),
+        });
+    }
+
+    // Leap day
+    let has_leap_day = seen.keys().any(|(m, d, _)| *m == 2 && *d == 29);
+    if has_leap_day {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            message: "The file contains February 29th, but simulations assume 365-day years".to_string(),
+        });
+    } else if leap_year_observed {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            message: "The header says leap years are observed, but there is no February 29th".to_string(),
+        });
+    }
+    if seen.keys().any(|(m, d, _)| *d > days_in_month(*m) && !(*m == 2 && *d == 29)) {
+        issues.push(Issue {
+            severity: Severity::Error,
+            message: "Some records have days that do not exist".to_string(),
+        });
+    }
+
+    // Values
+    for (j, (_, name, min, max, _)) in FIELD_RANGES.iter().enumerate() {
+        if !out_of_range[j].is_empty() {
+            issues.push(Issue {
+                severity: Severity::Error,
+                message: format!(
+                    "{} values of {} out of [{}, {}]: {}",
+                    out_of_range[j].len(),
+                    name,
+                    min,
+                    max,
+                    summarize(&out_of_range[j])
+                ),
+            });
+        }
+        if missing_values[j] > 0 {
+            issues.push(Issue {
+                severity: Severity::Warning,
+                message: format!("{} missing values of {}", missing_values[j], name),
+            });
+        }
+    }
+
+    let monthly = monthly_values
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| !v[0].is_empty())
+        .map(|(i, v)| MonthlyWeather {
+            month: i + 1,
+            temperature: Statistics::new(&v[0]),
+            global_horizontal_radiation: v[1].iter().sum::<f64>() / 1000.,
+            direct_normal_radiation: v[2].iter().sum::<f64>() / 1000.,
+            diffuse_horizontal_radiation: v[3].iter().sum::<f64>() / 1000.,
+        })
+        .collect();
+
+    WeatherReport {
+        filename: filename.to_string(),
+        city: location.as_ref().map(|l| l.city.clone()),
+        latitude: location.as_ref().map(|l| l.latitude),
+        longitude: location.as_ref().map(|l| l.longitude),
+        timezone: location.as_ref().map(|l| l.timezone),
+        records: file.records.len(),
+        issues,
+        monthly,
+    }
+}
+
+/// Reads and checks an EPW file, which should cover the whole year
+pub fn validate_epw_file(filename: &str) -> Result<WeatherReport, String> {
+    let file = EpwFile::from_file(filename)?;
+    Ok(validate_epw(filename, &file, None))
+}
+
+impl fmt::Display for WeatherReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Weather file {} ({} records)", self.filename, self.records)?;
+        if let (Some(city), Some(lat), Some(lon), Some(tz)) = (&self.city, self.latitude, self.longitude, self.timezone) {
+            writeln!(f, "{}: latitude {}, longitude {}, timezone {}", city, lat, lon, tz)?;
+        }
+        writeln!(
+            f,
+            "{:>6} {:>10} {:>10} {:>10} {:>14} {:>14} {:>14}",
+            "Month", "Tmin [C]", "Tmean [C]", "Tmax [C]", "GHI [kWh/m2]", "DNI [kWh/m2]", "DHI [kWh/m2]"
+        )?;
+        for m in self.monthly.iter() {
+            writeln!(
+                f,
+                "{:>6} {:>10.1} {:>10.1} {:>10.1} {:>14.1} {:>14.1} {:>14.1}",
+                m.month,
+                m.temperature.min,
+                m.temperature.mean,
+                m.temperature.max,
+                m.global_horizontal_radiation,
+                m.direct_normal_radiation,
+                m.diffuse_horizontal_radiation
+            )?;
+        }
+        if self.issues.is_empty() {
+            writeln!(f, "No issues found")?;
+        }
+        for issue in self.issues.iter() {
+            writeln!(f, "{:?}: {}", issue.severity, issue.message)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file with the records of some days of July
+    fn july(days: std::ops::RangeInclusive<usize>) -> EpwFile {
+        let mut content = String::from(
+            "LOCATION,Santiago,-,CHL,SRC-TMYx,855740,-33.38,-70.78,-4.0,482.0\n\
+             DESIGN CONDITIONS,0\n\
+             TYPICAL/EXTREME PERIODS,0\n\
+             GROUND TEMPERATURES,0\n\
+             HOLIDAYS/DAYLIGHT SAVINGS,No,0,0,0\n\
+             COMMENTS 1,\n\
+             COMMENTS 2,\n\
+             DATA PERIODS,1,1,Data,Sunday, 7/ 1,7/31\n",
+        );
+        for day in days {
+            for hour in 1..=24 {
+                let mut fields = vec!["0".to_string(); epw_file::FIELDS];
+                fields[epw_file::YEAR] = "2021".to_string();
+                fields[epw_file::MONTH] = "7".to_string();
+                fields[epw_file::DAY] = day.to_string();
+                fields[epw_file::HOUR] = hour.to_string();
+                fields[epw_file::DRY_BULB_TEMPERATURE] = "12.5".to_string();
+                fields[epw_file::DEW_POINT_TEMPERATURE] = "5.0".to_string();
+                fields[epw_file::RELATIVE_HUMIDITY] = "60".to_string();
+                fields[epw_file::ATMOSPHERIC_PRESSURE] = "95000".to_string();
+                content.push_str(&fields.join(","));
+                content.push('\n');
+            }
+        }
+        EpwFile::parse(&content).unwrap()
+    }
+
+    fn period(start_day: usize, end_day: usize) -> (Date, Date) {
+        (
+            Date {
+                month: 7,
+                day: start_day,
+                hour: 0.,
+            },
+            Date {
+                month: 7,
+                day: end_day,
+                hour: 0.,
+            },
+        )
+    }
+
+    #[test]
+    fn partial_year() {
+        let file = july(1..=3);
+
+        // A whole year is expected
+        let report = validate_epw("july.epw", &file, None);
+        assert!(!report.is_valid());
+        assert!(report.errors().contains("missing hours"));
+
+        // Only the simulated days are needed
+        let report = validate_epw("july.epw", &file, Some(&period(1, 3)));
+        assert!(report.is_valid(), "{}", report.errors());
+        assert!(report
+            .issues
+            .iter()
+            .any(|i| i.severity == Severity::Warning && i.message.contains("outside the simulated period")));
+
+        // ... which must be in the file
+        let report = validate_epw("july.epw", &file, Some(&period(3, 5)));
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn monthly_statistics() {
+        let report = validate_epw("july.epw", &july(1..=2), None);
+        assert_eq!(report.records, 48);
+        assert_eq!(report.monthly.len(), 1);
+        assert_eq!(report.monthly[0].month, 7);
+        assert!((report.monthly[0].temperature.mean - 12.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn periods() {
+        let p = period(1, 3);
+        assert!(!in_period(6, 30, 24, &p));
+        assert!(in_period(7, 1, 1, &p));
+        assert!(in_period(7, 2, 24, &p));
+        assert!(!in_period(7, 3, 1, &p));
+
+        // Through the new year
+        let p = (
+            Date {
+                month: 12,
+                day: 31,
+                hour: 0.,
+            },
+            Date {
+                month: 1,
+                day: 2,
+                hour: 0.,
+            },
+        );
+        assert!(in_period(12, 31, 24, &p));
+        assert!(in_period(1, 1, 1, &p));
+        assert!(!in_period(1, 2, 1, &p));
+        assert!(!in_period(12, 30, 24, &p));
+    }
+}
@@ -0,0 +1,225 @@
+//! Regression tests comparing the thesis cases against stored golden files.
+//!
+//! Each case is simulated for one day with a deterministic design-day
+//! weather, and the comfort, the actions taken and the main state elements
+//! of every timestep are compared with `tests/golden/<case>.json`.
+//!
+//! When a change in the physics or the people model is intended, the
+//! golden files are regenerated with
+//!
+//! ```bash
+//! SIMPLE_UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+//!
+//! which fails listing every value that changed, so that the differences
+//! are reviewed before committing the files. Running the tests again
+//! without the variable then passes.
+
+use std::path::PathBuf;
+
+use calendar::date::Date;
+use serde_json::{json, Value};
+
+use simple_lib::case::{Case, CaseDefinition};
+use simple_lib::results_json::{actions_taken, controller, get_bool, get_f64, timestep_start, timesteps};
+use simple_lib::runner::simulate_period;
+use simple_lib::state_elements::{
+    space_values, SPACE_DRY_BULB_TEMPERATURE, SPACE_HEATING_COOLING_POWER, SPACE_LIGHTING_POWER,
+};
+use simple_lib::weather_sources::DesignDayWeather;
+
+/// Timesteps per hour of the golden runs
+const N: usize = 6;
+
+/// Tolerance of the comfort of the person
+const COMFORT_TOLERANCE: f64 = 1e-4;
+
+/// Tolerance of space temperatures, in K
+const TEMPERATURE_TOLERANCE: f64 = 1e-3;
+
+/// Tolerance of heating and lighting powers, in W
+const POWER_TOLERANCE: f64 = 1e-2;
+
+/// A cold winter day in Santiago, so that heaters and lights are needed
+fn weather() -> DesignDayWeather {
+    DesignDayWeather {
+        max_dry_bulb_temperature: 16.,
+        daily_range: 10.,
+        relative_humidity: 70.,
+        peak_direct_normal_radiation: 600.,
+        peak_diffuse_horizontal_radiation: 120.,
+        sunrise: 7.5,
+        sunset: 18.,
+        wind_speed: 2.,
+    }
+}
+
+fn period() -> (Date, Date) {
+    let start = Date {
+        month: 7,
+        day: 1,
+        hour: 0.,
+    };
+    let mut end = start;
+    end.add_days(1);
+    (start, end)
+}
+
+/// The parts of the results that are compared
+fn snapshot(case: Case) -> Value {
+    let simulation = simulate_period(&CaseDefinition::new(case), &weather(), period(), N).unwrap();
+    let sorted = |mut v: Vec<(usize, f64)>| -> Vec<f64> {
+        v.sort_by_key(|(i, _)| *i);
+        v.into_iter().map(|(_, v)| v).collect()
+    };
+
+    let steps: Vec<Value> = timesteps(&simulation.results)
+        .unwrap()
+        .iter()
+        .map(|tstep| {
+            let person = controller(tstep, "person").unwrap();
+            let attended = get_bool(person, "attended").unwrap();
+            let elements = &tstep["state_elements"];
+            json!({
+                "date": format!("{}", timestep_start(tstep).unwrap()),
+                "attended": attended,
+                "current_comfort": if attended { get_f64(person, "current_comfort").ok() } else { None },
+                "actions": actions_taken(person).unwrap_or_default(),
+                "temperatures": sorted(space_values(elements, SPACE_DRY_BULB_TEMPERATURE)),
+                "heating": sorted(space_values(elements, SPACE_HEATING_COOLING_POWER)),
+                "lighting": sorted(space_values(elements, SPACE_LIGHTING_POWER)),
+            })
+        })
+        .collect();
+    json!({ "case": case.name(), "steps_per_hour": N, "timesteps": steps })
+}
+
+fn golden_file(case: Case) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.json", case.name()))
+}
+
+/// Compares two lists of numbers, describing the first difference
+fn compare_numbers(name: &str, date: &str, expected: &Value, actual: &Value, tolerance: f64) -> Option<String> {
+    let expected = expected.as_array()?;
+    let actual = actual.as_array()?;
+    if expected.len() != actual.len() {
+        return Some(format!("{}: {} {} values, found {}", date, name, expected.len(), actual.len()));
+    }
+    for (i, (e, a)) in expected.iter().zip(actual.iter()).enumerate() {
+        let (e, a) = (e.as_f64().unwrap_or(std::f64::NAN), a.as_f64().unwrap_or(std::f64::NAN));
+        let close = (e - a).abs() <= tolerance;
+        if !close {
+            return Some(format!("{}: {}[{}] was {}, now {} (tolerance {})", date, name, i, e, a, tolerance));
+        }
+    }
+    None
+}
+
+/// Describes every difference between a golden snapshot and a new one
+fn differences(expected: &Value, actual: &Value) -> Vec<String> {
+    let expected = expected["timesteps"].as_array().cloned().unwrap_or_default();
+    let actual = actual["timesteps"].as_array().cloned().unwrap_or_default();
+    if expected.len() != actual.len() {
+        return vec![format!("{} timesteps, found {}", expected.len(), actual.len())];
+    }
+
+    let mut differences = Vec::new();
+    for (e, a) in expected.iter().zip(actual.iter()) {
+        let date = e["date"].as_str().unwrap_or("?");
+        if e["date"] != a["date"] {
+            return vec![format!("timesteps are not aligned: {} and {}", e["date"], a["date"])];
+        }
+
+        if e["attended"] != a["attended"] {
+            differences.push(format!("{}: attended was {}, now {}", date, e["attended"], a["attended"]));
+        }
+        if e["actions"] != a["actions"] {
+            differences.push(format!("{}: actions were {}, now {}", date, e["actions"], a["actions"]));
+        }
+        let comfort = |v: &Value| json!([v["current_comfort"]]);
+        if e["current_comfort"].is_null() != a["current_comfort"].is_null() {
+            differences.push(format!("{}: comfort was {}, now {}", date, e["current_comfort"], a["current_comfort"]));
+        } else if !e["current_comfort"].is_null() {
+            differences.extend(compare_numbers("comfort", date, &comfort(e), &comfort(a), COMFORT_TOLERANCE));
+        }
+        differences.extend(compare_numbers("temperatures", date, &e["temperatures"], &a["temperatures"], TEMPERATURE_TOLERANCE));
+        differences.extend(compare_numbers("heating", date, &e["heating"], &a["heating"], POWER_TOLERANCE));
+        differences.extend(compare_numbers("lighting", date, &e["lighting"], &a["lighting"], POWER_TOLERANCE));
+    }
+    differences
+}
+
+/// Lists the first differences
+fn summary(differences: &[String]) -> String {
+    differences.iter().take(20).cloned().collect::<Vec<String>>().join("\n")
+}
+
+fn check(case: Case) {
+    let actual = snapshot(case);
+    let file = golden_file(case);
+    let expected: Option<Value> = std::fs::read_to_string(&file)
+        .ok()
+        .map(|v| serde_json::from_str(&v).unwrap());
+
+    // Updating never passes silently over changed values: the new file is
+    // written, and the test fails listing what changed, for review
+    if std::env::var("SIMPLE_UPDATE_GOLDEN").is_ok() {
+        let differences = expected.as_ref().map(|e| differences(e, &actual)).unwrap_or_default();
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, serde_json::to_string_pretty(&actual).unwrap()).unwrap();
+        if !differences.is_empty() {
+            panic!(
+                "Golden file '{}' updated; {} values changed:\n{}",
+                file.to_string_lossy(),
+                differences.len(),
+                summary(&differences)
+            );
+        }
+        return;
+    }
+
+    let expected = match expected {
+        Some(v) => v,
+        None => panic!(
+            "Golden file '{}' is missing; generate it with SIMPLE_UPDATE_GOLDEN=1",
+            file.to_string_lossy()
+        ),
+    };
+    let differences = differences(&expected, &actual);
+    if !differences.is_empty() {
+        panic!(
+            "{} differs from its golden file in {} places:\n{}",
+            case.name(),
+            differences.len(),
+            summary(&differences)
+        );
+    }
+}
+
+#[test]
+fn case1() {
+    check(Case::Section1_1);
+}
+
+#[test]
+fn case2_without_control() {
+    check(Case::Section1_2WithoutControl);
+}
+
+#[test]
+fn case2_with_control() {
+    check(Case::Section1_2WithControl);
+}
+
+#[test]
+fn case3_busy() {
+    check(Case::Section1_3Busy);
+}
+
+#[test]
+fn case3_not_busy() {
+    check(Case::Section1_3NotBusy);
+}
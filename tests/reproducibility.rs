@@ -0,0 +1,67 @@
+//! Checks that simulations are reproducible: two runs with the same
+//! inputs and seed must take exactly the same actions.
+
+use calendar::date::Date;
+use serde_json::Value;
+
+use simple_lib::case::{Case, CaseDefinition};
+use simple_lib::forecast::ForecastConfig;
+use simple_lib::results_json::{actions_taken, controller, timesteps};
+use simple_lib::runner::simulate_period;
+use simple_lib::weather_sources::DesignDayWeather;
+
+/// Timesteps per hour
+const N: usize = 6;
+
+fn weather() -> DesignDayWeather {
+    DesignDayWeather {
+        max_dry_bulb_temperature: 16.,
+        daily_range: 10.,
+        relative_humidity: 70.,
+        peak_direct_normal_radiation: 600.,
+        peak_diffuse_horizontal_radiation: 120.,
+        sunrise: 7.5,
+        sunset: 18.,
+        wind_speed: 2.,
+    }
+}
+
+fn period() -> (Date, Date) {
+    let start = Date {
+        month: 7,
+        day: 1,
+        hour: 0.,
+    };
+    let mut end = start;
+    end.add_days(1);
+    (start, end)
+}
+
+/// The actions taken in every timestep of a run of case 1,
+/// whose occupant sees a noisy forecast
+fn actions(seed: u64) -> Vec<Vec<(String, String)>> {
+    let mut definition = CaseDefinition::new(Case::from_name("case1").unwrap());
+    definition.options.seed = seed;
+    definition.options.forecast = Some(ForecastConfig {
+        temperature_bias: 0.1,
+        temperature_noise: 0.5,
+        radiation_bias: 0.0,
+        radiation_noise: 0.1,
+    });
+    let simulation = simulate_period(&definition, &weather(), period(), N).unwrap();
+    timesteps(&simulation.results)
+        .unwrap()
+        .iter()
+        .map(|tstep: &Value| actions_taken(controller(tstep, "person").unwrap()).unwrap())
+        .collect()
+}
+
+#[test]
+fn same_seed_same_actions() {
+    let first = actions(42);
+    let second = actions(42);
+    assert_eq!(first.len(), second.len());
+    for (i, (a, b)) in first.iter().zip(second.iter()).enumerate() {
+        assert_eq!(a, b, "Different actions in timestep {}", i);
+    }
+}